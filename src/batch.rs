@@ -0,0 +1,375 @@
+use std::any::Any;
+use std::future::Future;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{timeout_at, Instant};
+
+use crate::{BoxFuture, DynTryError, Foo, FooError, TryUpdateFn, UpdateFn, Zed};
+
+/// When `Batcher` stops collecting and flushes a batch to the store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchConfig {
+    pub max_batch: usize,
+    pub window: Duration,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            max_batch: 64,
+            window: Duration::from_millis(5),
+        }
+    }
+}
+
+enum Update {
+    Apply(UpdateFn),
+    Try(TryUpdateFn<DynTryError>),
+}
+
+struct Pending {
+    update: Update,
+    done: oneshot::Sender<Result<Zed, DynTryError>>,
+}
+
+/// A `Foo` that coalesces `bar` and `try_bar` calls into a single `try_bar`
+/// on the wrapped store.
+///
+/// The first pending update opens a batch; it is flushed once `window` has
+/// passed or `max_batch` updates are waiting. Updates run in submission
+/// order and every submitter receives the state after the whole batch. A
+/// rejected `try_bar` is skipped and only its submitter sees the error; an
+/// update that panics is skipped the same way and fails with
+/// `FooError::Panicked`. A batch in which every update failed commits
+/// nothing, and a store that panics fails its batch with
+/// `FooError::Panicked`.
+/// `get` and `async_bar` are not batched and go straight to the store.
+pub struct Batcher<F> {
+    tx: mpsc::UnboundedSender<Pending>,
+    store: Arc<F>,
+}
+
+impl<F: Foo + Send + Sync + 'static> Batcher<F> {
+    /// Starts the background flush task on the current tokio runtime. The
+    /// task exits once every handle to the `Batcher` has been dropped.
+    pub fn spawn(store: F, config: BatchConfig) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let store = Arc::new(store);
+        tokio::spawn(run(Arc::clone(&store), config, rx));
+        Self { tx, store }
+    }
+}
+
+impl<F> Batcher<F> {
+    async fn submit(&self, update: Update) -> Result<Zed, DynTryError> {
+        let (done, rx) = oneshot::channel();
+        self.tx
+            .send(Pending { update, done })
+            .map_err(|_| FooError::Closed)?;
+        rx.await.map_err(|_| FooError::Closed)?
+    }
+}
+
+impl<F> Clone for Batcher<F> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            store: Arc::clone(&self.store),
+        }
+    }
+}
+
+impl<F: Foo + Send + Sync> Foo for Batcher<F> {
+    fn get(&self) -> impl Future<Output = Result<Zed, FooError>> + Send {
+        self.store.get()
+    }
+
+    async fn bar<U>(&self, update_fn: U) -> Result<Zed, FooError>
+    where
+        U: FnOnce(Zed) -> Zed + Send + 'static,
+    {
+        self.submit(Update::Apply(Box::new(update_fn)))
+            .await
+            .map_err(DynTryError::into_error)
+    }
+
+    async fn try_bar<U, E>(&self, update_fn: U) -> Result<Zed, E>
+    where
+        U: FnOnce(Zed) -> Result<Zed, E> + Send + 'static,
+        E: From<FooError> + Send + 'static,
+    {
+        let update_fn: TryUpdateFn<DynTryError> =
+            Box::new(move |zed| update_fn(zed).map_err(DynTryError::rejected));
+        self.submit(Update::Try(update_fn))
+            .await
+            .map_err(DynTryError::into_error)
+    }
+
+    fn async_bar<U>(&self, update_fn: U) -> impl Future<Output = Result<Zed, FooError>> + Send
+    where
+        U: FnOnce(Zed) -> BoxFuture<'static, Zed> + Send + 'static,
+    {
+        self.store.async_bar(update_fn)
+    }
+}
+
+async fn run<F: Foo + Send + Sync + 'static>(
+    store: Arc<F>,
+    config: BatchConfig,
+    mut rx: mpsc::UnboundedReceiver<Pending>,
+) {
+    while let Some(first) = rx.recv().await {
+        let deadline = Instant::now() + config.window;
+        let mut batch = vec![first];
+        while batch.len() < config.max_batch {
+            match timeout_at(deadline, rx.recv()).await {
+                Ok(Some(pending)) => batch.push(pending),
+                Ok(None) | Err(_) => break,
+            }
+        }
+        let (updates, senders): (Vec<_>, Vec<_>) = batch
+            .into_iter()
+            .map(|pending| (pending.update, pending.done))
+            .unzip();
+
+        // A panicking store only fails this batch; later batches still go
+        // through.
+        let store = Arc::clone(&store);
+        match tokio::spawn(async move { flush(&*store, updates).await }).await {
+            Ok(outcomes) => {
+                for (done, outcome) in senders.into_iter().zip(outcomes) {
+                    let _ = done.send(outcome);
+                }
+            }
+            Err(err) => {
+                let err = if err.is_panic() {
+                    FooError::Panicked(panic_message(&*err.into_panic()))
+                } else {
+                    FooError::Closed
+                };
+                for done in senders {
+                    let _ = done.send(Err(err.clone().into()));
+                }
+            }
+        }
+    }
+}
+
+/// Why the combined update of a batch committed nothing.
+enum FlushError {
+    Store(FooError),
+    /// Every update in the batch was rejected or panicked.
+    NothingApplied,
+}
+
+impl From<FooError> for FlushError {
+    fn from(err: FooError) -> Self {
+        FlushError::Store(err)
+    }
+}
+
+/// Applies `updates` in one `try_bar` and returns each submitter's outcome.
+async fn flush<F: Foo>(store: &F, updates: Vec<Update>) -> Vec<Result<Zed, DynTryError>> {
+    let count = updates.len();
+    let rejections = Arc::new(Mutex::new(Vec::with_capacity(updates.len())));
+    let record = Arc::clone(&rejections);
+    let result = store
+        .try_bar(move |zed| {
+            let mut record = record.lock().unwrap();
+            let mut applied = false;
+            let zed = updates.into_iter().fold(zed, |zed, update| {
+                let input = zed.clone();
+                let outcome = catch_unwind(AssertUnwindSafe(move || match update {
+                    Update::Apply(update_fn) => Ok(update_fn(input)),
+                    Update::Try(update_fn) => update_fn(input),
+                }));
+                match outcome {
+                    Ok(Ok(next)) => {
+                        applied = true;
+                        record.push(None);
+                        next
+                    }
+                    Ok(Err(err)) => {
+                        record.push(Some(err));
+                        zed
+                    }
+                    Err(payload) => {
+                        record.push(Some(FooError::Panicked(panic_message(&*payload)).into()));
+                        zed
+                    }
+                }
+            });
+            // Reject the whole batch rather than have the store write back
+            // an unchanged state.
+            if applied {
+                Ok(zed)
+            } else {
+                Err(FlushError::NothingApplied)
+            }
+        })
+        .await;
+
+    // The store may fail before running the closure, so a store error is
+    // handed to every submitter rather than read off the rejections.
+    if let Err(FlushError::Store(err)) = &result {
+        return (0..count)
+            .map(|_| Err(DynTryError::Store(err.clone())))
+            .collect();
+    }
+    let rejections = std::mem::take(&mut *rejections.lock().unwrap());
+    rejections
+        .into_iter()
+        .map(|rejection| match (&result, rejection) {
+            (_, Some(err)) => Err(err),
+            (Ok(zed), None) => Ok(zed.clone()),
+            (Err(_), None) => unreachable!("a batch with a successful update was rejected"),
+        })
+        .collect()
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "non-string panic payload".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{BazError, BazImpl, ChaosFoo, ChaosPolicy, InMemoryFoo, MockFoo, VersionedFoo};
+
+    /// Like `capture::applying`, for the `try_bar` a batch is flushed with.
+    fn applying(
+        seed: Zed,
+    ) -> impl FnMut(TryUpdateFn<FlushError>) -> BoxFuture<'static, Result<Zed, FlushError>> {
+        move |update_fn| {
+            let result = update_fn(seed.clone());
+            Box::pin(async { result })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_burst_becomes_one_try_bar_call() {
+        let mut mock_foo = MockFoo::new();
+        mock_foo
+            .expect_try_bar::<FlushError>()
+            .times(1)
+            .returning(applying(Zed::default()));
+        let config = BatchConfig {
+            window: Duration::from_millis(50),
+            ..BatchConfig::default()
+        };
+        let baz = BazImpl::new(Batcher::spawn(mock_foo, config));
+
+        let (first, second, third) =
+            tokio::join!(baz.set_name("a"), baz.set_name("b"), baz.reset());
+
+        for result in [first, second, third] {
+            assert_eq!(result.unwrap(), Zed::default());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_batch_splits_bursts() {
+        let mut mock_foo = MockFoo::new();
+        mock_foo
+            .expect_try_bar::<FlushError>()
+            .times(2)
+            .returning(applying(Zed::default()));
+        let config = BatchConfig {
+            max_batch: 2,
+            window: Duration::from_secs(60),
+        };
+        let batcher = Batcher::spawn(mock_foo, config);
+
+        let (first, second, third, fourth) = tokio::join!(
+            batcher.bar(|zed| zed),
+            batcher.bar(|zed| zed),
+            batcher.bar(|zed| zed),
+            batcher.bar(|zed| zed)
+        );
+
+        for result in [first, second, third, fourth] {
+            result.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rejection_only_fails_its_submitter() {
+        let store = Arc::new(InMemoryFoo::default());
+        let baz = BazImpl::new(Batcher::spawn(Arc::clone(&store), BatchConfig::default()));
+
+        let (first, second, third) = tokio::join!(
+            baz.increment(1),
+            baz.append_entry(""),
+            baz.append_entry("a")
+        );
+
+        let expected = Zed::builder().counter(1).entry("a").build();
+        assert_eq!(first.unwrap(), expected);
+        assert!(matches!(second, Err(BazError::Rejected(_))));
+        assert_eq!(third.unwrap(), expected);
+        assert_eq!(store.snapshot().await, expected);
+    }
+
+    #[tokio::test]
+    async fn test_panicking_update_only_fails_its_submitter() {
+        let store = Arc::new(InMemoryFoo::default());
+        let batcher = Batcher::spawn(Arc::clone(&store), BatchConfig::default());
+
+        let (first, second) = tokio::join!(
+            batcher.bar(|mut zed| {
+                zed.counter += 1;
+                zed
+            }),
+            batcher.bar(|_| panic!("boom"))
+        );
+        assert_eq!(first.unwrap().counter, 1);
+        assert!(matches!(second, Err(FooError::Panicked(message)) if message == "boom"));
+
+        let later = batcher.bar(|zed| zed).await.unwrap();
+        assert_eq!(later.counter, 1);
+    }
+
+    #[tokio::test]
+    async fn test_batch_without_successes_writes_nothing() {
+        let store = Arc::new(VersionedFoo::default());
+        let batcher = Batcher::spawn(Arc::clone(&store), BatchConfig::default());
+
+        let (rejected, panicked) = tokio::join!(
+            batcher.try_bar(|_| Err::<Zed, _>(BazError::Rejected("nope".to_string()))),
+            batcher.bar(|_| panic!("boom"))
+        );
+        assert!(matches!(rejected, Err(BazError::Rejected(_))));
+        assert!(matches!(panicked, Err(FooError::Panicked(_))));
+        assert_eq!(store.snapshot().version, 0);
+
+        batcher.bar(|zed| zed).await.unwrap();
+        assert_eq!(store.snapshot().version, 1);
+    }
+
+    #[tokio::test]
+    async fn test_panicking_store_fails_the_batch_with_panicked() {
+        let policy = ChaosPolicy {
+            panic_rate: 1.0,
+            ..ChaosPolicy::default()
+        };
+        let batcher = Batcher::spawn(
+            ChaosFoo::new(InMemoryFoo::default(), policy),
+            BatchConfig::default(),
+        );
+
+        for _ in 0..2 {
+            let result = batcher.bar(|zed| zed).await;
+            assert!(
+                matches!(result, Err(FooError::Panicked(message)) if message.contains("injected"))
+            );
+        }
+    }
+}
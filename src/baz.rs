@@ -0,0 +1,235 @@
+use std::future::Future;
+
+#[cfg(feature = "metrics")]
+use crate::metrics::{self, Registry};
+use crate::trace;
+use crate::{BazError, Foo, FooError, Zed};
+
+/// Business operations on top of a `Foo` store.
+///
+/// The store is owned for the lifetime of the `BazImpl`; pass an `Arc` to
+/// share one store between several owners. All operations take `&self`, so
+/// a single `BazImpl` can serve concurrent callers.
+pub struct BazImpl<F> {
+    store: F,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Registry>,
+}
+
+impl<F: Foo> BazImpl<F> {
+    pub fn new(store: F) -> Self {
+        Self {
+            store,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        }
+    }
+
+    /// Counts and times every named operation in `registry`.
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(mut self, registry: Registry) -> Self {
+        metrics::describe_operations(&registry);
+        self.metrics = Some(registry);
+        self
+    }
+
+    pub fn store(&self) -> &F {
+        &self.store
+    }
+
+    pub async fn baz(&self) -> Result<Zed, FooError> {
+        self.store.bar(|zed| zed).await
+    }
+
+    /// Reads the current state. Unlike `baz`, nothing is written back.
+    pub async fn get(&self) -> Result<Zed, FooError> {
+        self.store.get().await
+    }
+
+    /// Runs a validating update. A rejection leaves the store untouched.
+    pub async fn try_baz<U>(&self, update_fn: U) -> Result<Zed, BazError>
+    where
+        U: FnOnce(Zed) -> Result<Zed, BazError> + Send + 'static,
+    {
+        self.store.try_bar(update_fn).await
+    }
+
+    pub async fn set_name(&self, name: impl Into<String>) -> Result<Zed, BazError> {
+        let name = name.into();
+        self.operation("set_name", async {
+            let zed = self
+                .store
+                .bar(move |mut zed| {
+                    zed.name = name;
+                    zed
+                })
+                .await?;
+            Ok(zed)
+        })
+        .await
+    }
+
+    /// Adds `by` to the counter, rejecting the update if it would overflow.
+    pub async fn increment(&self, by: u64) -> Result<Zed, BazError> {
+        self.operation(
+            "increment",
+            self.try_baz(move |mut zed| {
+                zed.counter = zed.counter.checked_add(by).ok_or_else(|| {
+                    BazError::Rejected(format!("counter {} + {by} overflows", zed.counter))
+                })?;
+                Ok(zed)
+            }),
+        )
+        .await
+    }
+
+    /// Appends `entry`, rejecting empty entries.
+    pub async fn append_entry(&self, entry: impl Into<String>) -> Result<Zed, BazError> {
+        let entry = entry.into();
+        self.operation(
+            "append_entry",
+            self.try_baz(move |mut zed| {
+                if entry.is_empty() {
+                    return Err(BazError::Rejected("entry is empty".to_string()));
+                }
+                zed.entries.push(entry);
+                Ok(zed)
+            }),
+        )
+        .await
+    }
+
+    /// Puts the state back to `Zed::default()`.
+    pub async fn reset(&self) -> Result<Zed, BazError> {
+        self.operation("reset", async {
+            let zed = self.store.bar(|_| Zed::default()).await?;
+            Ok(zed)
+        })
+        .await
+    }
+
+    async fn operation<O>(&self, name: &'static str, op: O) -> Result<Zed, BazError>
+    where
+        O: Future<Output = Result<Zed, BazError>>,
+    {
+        let op = trace::operation(name, op);
+        #[cfg(feature = "metrics")]
+        if let Some(registry) = &self.metrics {
+            return metrics::operation(registry, name, op).await;
+        }
+        op.await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::capture::{CaptureQueue, OnceCapture};
+    use crate::{InMemoryFoo, MockFoo, TryUpdateFn, UpdateFn};
+
+    #[tokio::test]
+    async fn test_try_baz_captures_fallible_update() {
+        let captured_update_fn = OnceCapture::<TryUpdateFn<BazError>>::new();
+
+        let mut mock_foo = MockFoo::new();
+        mock_foo
+            .expect_try_bar::<BazError>()
+            .times(1)
+            .returning(captured_update_fn.returning_fn());
+
+        let baz = BazImpl::new(mock_foo);
+        baz.try_baz(|zed| {
+            if zed.counter == 0 {
+                Err(BazError::Rejected("counter is zero".to_string()))
+            } else {
+                Ok(zed)
+            }
+        })
+        .await
+        .unwrap();
+
+        let result = captured_update_fn.invoke(Zed::default());
+        assert!(matches!(result, Err(BazError::Rejected(_))));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_calls_share_one_store() {
+        let seed = Zed::builder().name("seed").entry("a").build();
+        let baz = BazImpl::new(InMemoryFoo::new(seed.clone()));
+
+        let increment = |mut zed: Zed| {
+            zed.counter += 1;
+            Ok(zed)
+        };
+        let (first, second, third) =
+            tokio::join!(baz.try_baz(increment), baz.try_baz(increment), baz.baz());
+        first.unwrap();
+        second.unwrap();
+        third.unwrap();
+
+        let zed = baz.baz().await.unwrap();
+        assert_eq!(zed.counter, 2);
+        assert_eq!(zed.name, seed.name);
+        assert_eq!(baz.store().snapshot().await, zed);
+    }
+
+    #[tokio::test]
+    async fn test_set_name_and_reset() {
+        let queue = CaptureQueue::<UpdateFn>::new();
+        let mut mock_foo = MockFoo::new();
+        mock_foo
+            .expect_bar()
+            .times(2)
+            .returning(queue.returning_fn());
+
+        let baz = BazImpl::new(mock_foo);
+        baz.set_name("renamed").await.unwrap();
+        baz.reset().await.unwrap();
+
+        queue.assert_captured_count(2);
+        let seed = Zed::builder().name("seed").counter(4).entry("a").build();
+        assert_eq!(
+            queue.invoke(0, seed.clone()),
+            Zed::builder().name("renamed").counter(4).entry("a").build()
+        );
+        assert_eq!(queue.invoke(1, seed), Zed::default());
+    }
+
+    #[tokio::test]
+    async fn test_increment_and_append_entry() {
+        let queue = CaptureQueue::<TryUpdateFn<BazError>>::new();
+        let mut mock_foo = MockFoo::new();
+        mock_foo
+            .expect_try_bar::<BazError>()
+            .times(4)
+            .returning(queue.returning_fn());
+
+        let baz = BazImpl::new(mock_foo);
+        baz.increment(2).await.unwrap();
+        baz.increment(1).await.unwrap();
+        baz.append_entry("b").await.unwrap();
+        baz.append_entry("").await.unwrap();
+
+        let seed = Zed::builder().counter(u64::MAX - 1).entry("a").build();
+        assert_eq!(
+            queue.invoke(0, Zed::default()).unwrap(),
+            Zed::builder().counter(2).build()
+        );
+        assert!(matches!(
+            queue.invoke(1, seed.clone()),
+            Ok(zed) if zed.counter == u64::MAX
+        ));
+        assert_eq!(queue.invoke(2, seed.clone()).unwrap().entries, ["a", "b"]);
+        assert!(matches!(queue.invoke(3, seed), Err(BazError::Rejected(_))));
+    }
+
+    #[tokio::test]
+    async fn test_rejected_increment_keeps_state() {
+        let seed = Zed::builder().counter(u64::MAX).build();
+        let baz = BazImpl::new(InMemoryFoo::new(seed.clone()));
+
+        assert!(matches!(baz.increment(1).await, Err(BazError::Rejected(_))));
+        assert_eq!(baz.store().snapshot().await, seed);
+    }
+}
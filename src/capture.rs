@@ -0,0 +1,296 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{AsyncUpdateFn, BoxFuture, FooError, TryUpdateFn, UpdateFn, Zed};
+
+enum Slot<F> {
+    Empty,
+    Held(F),
+    Taken,
+}
+
+/// Captures the single closure handed to a mocked method so a test can
+/// invoke it after the code under test has returned.
+///
+/// Clones share the same slot, so one handle can be moved into the mock
+/// while the test keeps another.
+pub struct OnceCapture<F> {
+    slot: Arc<Mutex<Slot<F>>>,
+}
+
+impl<F> OnceCapture<F> {
+    pub fn new() -> Self {
+        Self {
+            slot: Arc::new(Mutex::new(Slot::Empty)),
+        }
+    }
+
+    /// Stores `f`. Panics if a closure was already captured.
+    pub fn capture(&self, f: F) {
+        let mut slot = self.slot.lock().unwrap();
+        if !matches!(*slot, Slot::Empty) {
+            drop(slot);
+            panic!("OnceCapture: a closure was already captured");
+        }
+        *slot = Slot::Held(f);
+    }
+
+    /// Moves the captured closure out, leaving the slot spent.
+    pub fn take(&self) -> Option<F> {
+        let mut slot = self.slot.lock().unwrap();
+        match std::mem::replace(&mut *slot, Slot::Taken) {
+            Slot::Held(f) => Some(f),
+            Slot::Empty => {
+                *slot = Slot::Empty;
+                None
+            }
+            Slot::Taken => None,
+        }
+    }
+
+    /// Whether a closure has been captured, even if it was since taken.
+    pub fn was_captured(&self) -> bool {
+        !matches!(*self.slot.lock().unwrap(), Slot::Empty)
+    }
+}
+
+impl<A, R> OnceCapture<Box<dyn FnOnce(A) -> R + Send>> {
+    /// Takes the captured closure and calls it with `arg`.
+    pub fn invoke(&self, arg: A) -> R {
+        let f = self
+            .take()
+            .expect("OnceCapture: no closure available to invoke");
+        f(arg)
+    }
+}
+
+impl OnceCapture<UpdateFn> {
+    /// Adapter for `MockFoo::expect_bar().returning(...)`. The mocked call
+    /// resolves to `Zed::default()` since the closure is stored, not run.
+    pub fn returning_fn(
+        &self,
+    ) -> impl FnMut(UpdateFn) -> BoxFuture<'static, Result<Zed, FooError>> + Send + 'static {
+        let capture = self.clone();
+        move |update_fn| {
+            capture.capture(update_fn);
+            Box::pin(async { Ok(Zed::default()) })
+        }
+    }
+}
+
+impl<E: Send + 'static> OnceCapture<TryUpdateFn<E>> {
+    /// Adapter for `MockFoo::expect_try_bar::<E>().returning(...)`. The
+    /// mocked call resolves to `Ok(Zed::default())` since the closure is
+    /// stored, not run.
+    pub fn returning_fn(
+        &self,
+    ) -> impl FnMut(TryUpdateFn<E>) -> BoxFuture<'static, Result<Zed, E>> + Send + 'static {
+        let capture = self.clone();
+        move |update_fn| {
+            capture.capture(update_fn);
+            Box::pin(async { Ok(Zed::default()) })
+        }
+    }
+}
+
+impl OnceCapture<AsyncUpdateFn> {
+    /// Adapter for `MockFoo::expect_async_bar().returning(...)`. The mocked
+    /// call resolves to `Zed::default()` since the closure is stored, not run.
+    pub fn returning_fn(
+        &self,
+    ) -> impl FnMut(AsyncUpdateFn) -> BoxFuture<'static, Result<Zed, FooError>> + Send + 'static
+    {
+        let capture = self.clone();
+        move |update_fn| {
+            capture.capture(update_fn);
+            Box::pin(async { Ok(Zed::default()) })
+        }
+    }
+}
+
+impl<F> Clone for OnceCapture<F> {
+    fn clone(&self) -> Self {
+        Self {
+            slot: Arc::clone(&self.slot),
+        }
+    }
+}
+
+impl<F> Default for OnceCapture<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Records every closure handed to a mocked method, in call order.
+///
+/// Use this instead of [`OnceCapture`] when the code under test calls the
+/// method more than once.
+pub struct CaptureQueue<F> {
+    calls: Arc<Mutex<Vec<Option<F>>>>,
+}
+
+impl<F> CaptureQueue<F> {
+    pub fn new() -> Self {
+        Self {
+            calls: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Appends `f` to the queue.
+    pub fn capture(&self, f: F) {
+        self.calls.lock().unwrap().push(Some(f));
+    }
+
+    /// Number of closures captured so far, including ones already taken.
+    pub fn captured_count(&self) -> usize {
+        self.calls.lock().unwrap().len()
+    }
+
+    /// Moves out the closure from the `index`th call.
+    pub fn take(&self, index: usize) -> Option<F> {
+        self.calls
+            .lock()
+            .unwrap()
+            .get_mut(index)
+            .and_then(Option::take)
+    }
+
+    /// Moves out every closure not yet taken, in call order.
+    pub fn drain(&self) -> Vec<F> {
+        self.calls
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .filter_map(Option::take)
+            .collect()
+    }
+
+    #[track_caller]
+    pub fn assert_captured_count(&self, expected: usize) {
+        let actual = self.captured_count();
+        assert_eq!(
+            actual, expected,
+            "CaptureQueue: expected {expected} captured closures, got {actual}"
+        );
+    }
+}
+
+impl<A, R> CaptureQueue<Box<dyn FnOnce(A) -> R + Send>> {
+    /// Takes the closure from the `index`th call and calls it with `arg`.
+    pub fn invoke(&self, index: usize, arg: A) -> R {
+        let f = self
+            .take(index)
+            .unwrap_or_else(|| panic!("CaptureQueue: no closure available at index {index}"));
+        f(arg)
+    }
+}
+
+impl CaptureQueue<UpdateFn> {
+    /// Adapter for `MockFoo::expect_bar().returning(...)`. Each mocked call
+    /// resolves to `Zed::default()` since the closure is stored, not run.
+    pub fn returning_fn(
+        &self,
+    ) -> impl FnMut(UpdateFn) -> BoxFuture<'static, Result<Zed, FooError>> + Send + 'static {
+        let queue = self.clone();
+        move |update_fn| {
+            queue.capture(update_fn);
+            Box::pin(async { Ok(Zed::default()) })
+        }
+    }
+}
+
+impl<E: Send + 'static> CaptureQueue<TryUpdateFn<E>> {
+    /// Adapter for `MockFoo::expect_try_bar::<E>().returning(...)`. Each
+    /// mocked call resolves to `Ok(Zed::default())` since the closure is
+    /// stored, not run.
+    pub fn returning_fn(
+        &self,
+    ) -> impl FnMut(TryUpdateFn<E>) -> BoxFuture<'static, Result<Zed, E>> + Send + 'static {
+        let queue = self.clone();
+        move |update_fn| {
+            queue.capture(update_fn);
+            Box::pin(async { Ok(Zed::default()) })
+        }
+    }
+}
+
+impl<F> Clone for CaptureQueue<F> {
+    fn clone(&self) -> Self {
+        Self {
+            calls: Arc::clone(&self.calls),
+        }
+    }
+}
+
+impl<F> Default for CaptureQueue<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Adapter for `MockFoo::expect_bar().returning(...)` that runs each update
+/// closure against a clone of `seed` and resolves to the result.
+pub fn applying(
+    seed: Zed,
+) -> impl FnMut(UpdateFn) -> BoxFuture<'static, Result<Zed, FooError>> + Send + 'static {
+    move |update_fn| {
+        let zed = update_fn(seed.clone());
+        Box::pin(async { Ok(zed) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{Foo, MockFoo, Zed};
+
+    #[tokio::test]
+    #[should_panic(expected = "already captured")]
+    async fn test_double_capture_panics() {
+        let capture = OnceCapture::<UpdateFn>::new();
+
+        let mut mock_foo = MockFoo::new();
+        mock_foo
+            .expect_bar()
+            .times(2)
+            .returning(capture.returning_fn());
+
+        let _ = mock_foo.bar(|zed| zed).await;
+        let _ = mock_foo.bar(|zed| zed).await;
+    }
+
+    #[test]
+    fn test_take_empties_slot() {
+        let capture = OnceCapture::<UpdateFn>::new();
+        assert!(!capture.was_captured());
+        assert!(capture.take().is_none());
+
+        capture.capture(Box::new(|zed| zed));
+        assert!(capture.take().is_some());
+        assert!(capture.take().is_none());
+        assert!(capture.was_captured());
+    }
+
+    #[tokio::test]
+    async fn test_queue_keeps_every_call() {
+        let queue = CaptureQueue::<UpdateFn>::new();
+
+        let mut mock_foo = MockFoo::new();
+        mock_foo
+            .expect_bar()
+            .times(3)
+            .returning(queue.returning_fn());
+
+        for _ in 0..3 {
+            mock_foo.bar(|zed| zed).await.unwrap();
+        }
+
+        queue.assert_captured_count(3);
+        queue.invoke(1, Zed::default());
+        assert!(queue.take(1).is_none());
+        assert_eq!(queue.drain().len(), 2);
+        assert!(queue.drain().is_empty());
+        queue.assert_captured_count(3);
+    }
+}
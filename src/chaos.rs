@@ -0,0 +1,252 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::{BoxFuture, Foo, FooError, Zed};
+
+/// Which faults `ChaosFoo` injects and how often.
+///
+/// Rates are probabilities per call. A call first sleeps for a latency drawn
+/// from `latency`, then panics, fails, drops its update or passes through,
+/// in that order of precedence. The same `seed` always yields the same
+/// sequence of faults.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChaosPolicy {
+    pub seed: u64,
+    pub latency: Option<(Duration, Duration)>,
+    pub panic_rate: f64,
+    pub error_rate: f64,
+    pub drop_rate: f64,
+}
+
+impl Default for ChaosPolicy {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            latency: None,
+            panic_rate: 0.0,
+            error_rate: 0.0,
+            drop_rate: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fault {
+    None,
+    Panic,
+    Error,
+    Drop,
+}
+
+/// A `Foo` decorator that injects faults into calls to the inner store.
+///
+/// A dropped update never reaches the inner store, yet the call still
+/// succeeds with the current state, like a write that was acknowledged and
+/// then lost.
+pub struct ChaosFoo<F> {
+    inner: F,
+    policy: ChaosPolicy,
+    rng: Mutex<SplitMix64>,
+}
+
+impl<F: Foo> ChaosFoo<F> {
+    pub fn new(inner: F, policy: ChaosPolicy) -> Self {
+        let rng = Mutex::new(SplitMix64(policy.seed));
+        Self { inner, policy, rng }
+    }
+
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    async fn disrupt(&self) -> Fault {
+        let (latency, fault) = {
+            let mut rng = self.rng.lock().unwrap();
+            let latency = self
+                .policy
+                .latency
+                .map(|(min, max)| min + max.saturating_sub(min).mul_f64(rng.next_f64()));
+
+            let roll = rng.next_f64();
+            let ChaosPolicy {
+                panic_rate,
+                error_rate,
+                drop_rate,
+                ..
+            } = self.policy;
+            let fault = if roll < panic_rate {
+                Fault::Panic
+            } else if roll < panic_rate + error_rate {
+                Fault::Error
+            } else if roll < panic_rate + error_rate + drop_rate {
+                Fault::Drop
+            } else {
+                Fault::None
+            };
+            (latency, fault)
+        };
+
+        if let Some(latency) = latency {
+            tokio::time::sleep(latency).await;
+        }
+        if fault == Fault::Panic {
+            panic!("ChaosFoo: injected panic");
+        }
+        fault
+    }
+}
+
+impl<F: Foo + Sync> Foo for ChaosFoo<F> {
+    async fn get(&self) -> Result<Zed, FooError> {
+        match self.disrupt().await {
+            Fault::Error => Err(injected()),
+            _ => self.inner.get().await,
+        }
+    }
+
+    async fn bar<U>(&self, update_fn: U) -> Result<Zed, FooError>
+    where
+        U: FnOnce(Zed) -> Zed + Send + 'static,
+    {
+        match self.disrupt().await {
+            Fault::Error => Err(injected()),
+            Fault::Drop => self.inner.get().await,
+            _ => self.inner.bar(update_fn).await,
+        }
+    }
+
+    async fn try_bar<U, E>(&self, update_fn: U) -> Result<Zed, E>
+    where
+        U: FnOnce(Zed) -> Result<Zed, E> + Send + 'static,
+        E: From<FooError> + Send + 'static,
+    {
+        match self.disrupt().await {
+            Fault::Error => Err(injected().into()),
+            Fault::Drop => Ok(self.inner.get().await?),
+            _ => self.inner.try_bar(update_fn).await,
+        }
+    }
+
+    async fn async_bar<U>(&self, update_fn: U) -> Result<Zed, FooError>
+    where
+        U: FnOnce(Zed) -> BoxFuture<'static, Zed> + Send + 'static,
+    {
+        match self.disrupt().await {
+            Fault::Error => Err(injected()),
+            Fault::Drop => self.inner.get().await,
+            _ => self.inner.async_bar(update_fn).await,
+        }
+    }
+}
+
+fn injected() -> FooError {
+    FooError::Unavailable("ChaosFoo: injected failure".to_string())
+}
+
+/// A tiny deterministic generator; quality is plenty for picking faults.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{BazError, BazImpl, InMemoryFoo, VersionedFoo};
+
+    async fn outcomes(seed: u64) -> (Vec<&'static str>, Zed) {
+        let policy = ChaosPolicy {
+            seed,
+            error_rate: 0.3,
+            drop_rate: 0.3,
+            ..ChaosPolicy::default()
+        };
+        let baz = BazImpl::new(ChaosFoo::new(InMemoryFoo::default(), policy));
+
+        let mut outcomes = Vec::new();
+        let mut applied = 0;
+        for _ in 0..64 {
+            let before = baz.store().inner().snapshot().await.counter;
+            outcomes.push(match baz.increment(1).await {
+                Err(BazError::Store(FooError::Unavailable(_))) => "error",
+                Ok(zed) if zed.counter == before => "dropped",
+                Ok(_) => {
+                    applied += 1;
+                    "applied"
+                }
+                Err(err) => panic!("unexpected error {err}"),
+            });
+        }
+        let zed = baz.store().inner().snapshot().await;
+        assert_eq!(zed.counter, applied);
+        (outcomes, zed)
+    }
+
+    #[tokio::test]
+    async fn test_same_seed_same_faults() {
+        let (first, zed) = outcomes(7).await;
+        assert_eq!(outcomes(7).await, (first.clone(), zed));
+        assert_ne!(outcomes(8).await.0, first);
+        for kind in ["error", "dropped", "applied"] {
+            assert!(first.contains(&kind), "no {kind} outcome in {first:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dropped_update_writes_nothing() {
+        let policy = ChaosPolicy {
+            drop_rate: 1.0,
+            ..ChaosPolicy::default()
+        };
+        let foo = ChaosFoo::new(VersionedFoo::default(), policy);
+
+        let zed = foo
+            .bar(|mut zed| {
+                zed.counter += 1;
+                zed
+            })
+            .await
+            .unwrap();
+        foo.try_bar(Ok::<_, FooError>).await.unwrap();
+        foo.async_bar(|zed| Box::pin(async { zed })).await.unwrap();
+
+        assert_eq!(zed, Zed::default());
+        assert_eq!(foo.inner().snapshot().version, 0);
+    }
+
+    #[tokio::test]
+    async fn test_latency_is_injected() {
+        let policy = ChaosPolicy {
+            latency: Some((Duration::from_millis(20), Duration::from_millis(30))),
+            ..ChaosPolicy::default()
+        };
+        let foo = ChaosFoo::new(InMemoryFoo::default(), policy);
+
+        let started = tokio::time::Instant::now();
+        foo.bar(|zed| zed).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "injected panic")]
+    async fn test_panic_is_injected() {
+        let policy = ChaosPolicy {
+            panic_rate: 1.0,
+            ..ChaosPolicy::default()
+        };
+        let foo = ChaosFoo::new(InMemoryFoo::default(), policy);
+        let _ = foo.bar(|zed| zed).await;
+    }
+}
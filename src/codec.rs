@@ -0,0 +1,310 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{FooError, Zed};
+
+/// Turns a `Zed` into bytes and back, for stores that persist it.
+pub trait Codec: Send + Sync {
+    fn encode(&self, zed: &Zed) -> Vec<u8>;
+
+    fn decode(&self, bytes: &[u8]) -> Result<Zed, FooError>;
+}
+
+impl<C: Codec + ?Sized> Codec for Box<C> {
+    fn encode(&self, zed: &Zed) -> Vec<u8> {
+        (**self).encode(zed)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Zed, FooError> {
+        (**self).decode(bytes)
+    }
+}
+
+/// The built-in codecs, selectable by name.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Text,
+    Binary,
+    #[cfg(feature = "serde")]
+    Json,
+}
+
+impl Format {
+    pub fn codec(self) -> Box<dyn Codec> {
+        match self {
+            Format::Text => Box::new(TextCodec),
+            Format::Binary => Box::new(BinaryCodec),
+            #[cfg(feature = "serde")]
+            Format::Json => Box::new(JsonCodec),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Format::Text => "text",
+            Format::Binary => "binary",
+            #[cfg(feature = "serde")]
+            Format::Json => "json",
+        })
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "binary" => Ok(Format::Binary),
+            #[cfg(feature = "serde")]
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown format {s:?}")),
+        }
+    }
+}
+
+/// One `key value` line per field, with `\`, `\n` and `\r` escaped.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TextCodec;
+
+impl Codec for TextCodec {
+    fn encode(&self, zed: &Zed) -> Vec<u8> {
+        let mut text = format!(
+            "name {}\ncounter {}\nversion {}\n",
+            escape(&zed.name),
+            zed.counter,
+            zed.version
+        );
+        for entry in &zed.entries {
+            text.push_str("entry ");
+            text.push_str(&escape(entry));
+            text.push('\n');
+        }
+        text.into_bytes()
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Zed, FooError> {
+        let text = std::str::from_utf8(bytes)
+            .map_err(|err| FooError::Corrupt(format!("state is not UTF-8: {err}")))?;
+        let mut zed = Zed::default();
+        for line in text.split_terminator('\n') {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "name" => zed.name = unescape(value)?,
+                "counter" => {
+                    zed.counter = value
+                        .parse()
+                        .map_err(|_| FooError::Corrupt(format!("invalid counter {value:?}")))?
+                }
+                "version" => {
+                    zed.version = value
+                        .parse()
+                        .map_err(|_| FooError::Corrupt(format!("invalid version {value:?}")))?
+                }
+                "entry" => zed.entries.push(unescape(value)?),
+                _ => return Err(FooError::Corrupt(format!("unknown field {key:?}"))),
+            }
+        }
+        Ok(zed)
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn unescape(value: &str) -> Result<String, FooError> {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => out.push('\\'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            other => return Err(FooError::Corrupt(format!("invalid escape {other:?}"))),
+        }
+    }
+    Ok(out)
+}
+
+const BINARY_MAGIC: [u8; 2] = [b'Z', 1];
+
+/// A compact binary layout: a two byte header, then `version`, `counter`,
+/// `name` and `entries` as LEB128 varints and length-prefixed UTF-8.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BinaryCodec;
+
+impl Codec for BinaryCodec {
+    fn encode(&self, zed: &Zed) -> Vec<u8> {
+        let mut out = BINARY_MAGIC.to_vec();
+        put_varint(&mut out, zed.version);
+        put_varint(&mut out, zed.counter);
+        put_str(&mut out, &zed.name);
+        put_varint(&mut out, zed.entries.len() as u64);
+        for entry in &zed.entries {
+            put_str(&mut out, entry);
+        }
+        out
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Zed, FooError> {
+        let mut input = bytes
+            .strip_prefix(&BINARY_MAGIC)
+            .ok_or_else(|| FooError::Corrupt("missing binary header".to_string()))?;
+        let version = take_varint(&mut input)?;
+        let counter = take_varint(&mut input)?;
+        let name = take_str(&mut input)?;
+        let count = take_varint(&mut input)?;
+        let entries = (0..count)
+            .map(|_| take_str(&mut input))
+            .collect::<Result<_, _>>()?;
+        if !input.is_empty() {
+            return Err(FooError::Corrupt(format!(
+                "{} trailing bytes after state",
+                input.len()
+            )));
+        }
+        Ok(Zed {
+            name,
+            counter,
+            entries,
+            version,
+        })
+    }
+}
+
+fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn put_str(out: &mut Vec<u8>, value: &str) {
+    put_varint(out, value.len() as u64);
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn take_varint(input: &mut &[u8]) -> Result<u64, FooError> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = input
+            .split_first()
+            .ok_or_else(|| FooError::Corrupt("truncated varint".to_string()))?;
+        *input = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(FooError::Corrupt("varint is too long".to_string()))
+}
+
+fn take_str(input: &mut &[u8]) -> Result<String, FooError> {
+    let len = usize::try_from(take_varint(input)?)
+        .map_err(|_| FooError::Corrupt("string length overflows".to_string()))?;
+    if input.len() < len {
+        return Err(FooError::Corrupt("truncated string".to_string()));
+    }
+    let (bytes, rest) = input.split_at(len);
+    *input = rest;
+    String::from_utf8(bytes.to_vec())
+        .map_err(|err| FooError::Corrupt(format!("string is not UTF-8: {err}")))
+}
+
+/// `Zed` as a JSON object, via its serde derives.
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec;
+
+#[cfg(feature = "serde")]
+impl Codec for JsonCodec {
+    fn encode(&self, zed: &Zed) -> Vec<u8> {
+        serde_json::to_vec(zed).expect("Zed always serializes to JSON")
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Zed, FooError> {
+        serde_json::from_slice(bytes).map_err(|err| FooError::Corrupt(err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn golden_zed() -> Zed {
+        Zed::builder()
+            .name("golden\\zed\n")
+            .counter(300)
+            .entry("a")
+            .entry("ünïcode")
+            .version(7)
+            .build()
+    }
+
+    fn assert_golden(codec: &dyn Codec, golden: &[u8]) {
+        assert_eq!(codec.encode(&golden_zed()), golden);
+        assert_eq!(codec.decode(golden).unwrap(), golden_zed());
+    }
+
+    #[test]
+    fn test_text_golden() {
+        assert_golden(&TextCodec, include_bytes!("../tests/golden/zed.txt"));
+    }
+
+    #[test]
+    fn test_binary_golden() {
+        assert_golden(&BinaryCodec, include_bytes!("../tests/golden/zed.bin"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_json_golden() {
+        assert_golden(&JsonCodec, include_bytes!("../tests/golden/zed.json"));
+    }
+
+    #[test]
+    fn test_text_keeps_carriage_returns() {
+        let zed = Zed::builder()
+            .name("a\r")
+            .entry("b\r")
+            .entry("\r\n")
+            .build();
+        let bytes = TextCodec.encode(&zed);
+        assert!(!bytes.contains(&b'\r'));
+        assert_eq!(TextCodec.decode(&bytes).unwrap(), zed);
+    }
+
+    #[test]
+    fn test_binary_rejects_truncation() {
+        let bytes = BinaryCodec.encode(&golden_zed());
+        for len in 0..bytes.len() {
+            assert!(BinaryCodec.decode(&bytes[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_format_names_round_trip() {
+        for format in [
+            Format::Text,
+            Format::Binary,
+            #[cfg(feature = "serde")]
+            Format::Json,
+        ] {
+            assert_eq!(format.to_string().parse::<Format>(), Ok(format));
+        }
+        assert!("yaml".parse::<Format>().is_err());
+    }
+}
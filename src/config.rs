@@ -0,0 +1,317 @@
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::codec::Format;
+use crate::{Durability, WalConfig};
+
+/// Prefix of the environment variables read by `ConfigLayer::from_env`.
+pub const ENV_PREFIX: &str = "ZED_";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreKind {
+    Memory,
+    File,
+    Wal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+/// The effective settings of the binary, after every layer is merged.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Config {
+    pub store: StoreKind,
+    pub data_dir: PathBuf,
+    #[serde(serialize_with = "display")]
+    pub codec: Format,
+    #[serde(serialize_with = "display")]
+    pub durability: Durability,
+    pub compact_after: usize,
+    pub timeout_ms: u64,
+    pub log_level: LogLevel,
+    /// Daemon socket; `zed.sock` in `data_dir` when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub socket: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let wal = WalConfig::default();
+        Self {
+            store: StoreKind::Memory,
+            data_dir: PathBuf::from("."),
+            codec: Format::Text,
+            durability: wal.durability,
+            compact_after: wal.compact_after,
+            timeout_ms: 5_000,
+            log_level: LogLevel::Warn,
+            socket: None,
+        }
+    }
+}
+
+impl Config {
+    /// Overrides every setting that `layer` sets.
+    pub fn merge(self, layer: ConfigLayer) -> Self {
+        Self {
+            store: layer.store.unwrap_or(self.store),
+            data_dir: layer.data_dir.unwrap_or(self.data_dir),
+            codec: layer.codec.unwrap_or(self.codec),
+            durability: layer.durability.unwrap_or(self.durability),
+            compact_after: layer.compact_after.unwrap_or(self.compact_after),
+            timeout_ms: layer.timeout_ms.unwrap_or(self.timeout_ms),
+            log_level: layer.log_level.unwrap_or(self.log_level),
+            socket: layer.socket.or(self.socket),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |key, message: &str| {
+            Err(ConfigError::Invalid {
+                key,
+                message: message.to_string(),
+            })
+        };
+        if self.data_dir.as_os_str().is_empty() {
+            return invalid("data_dir", "must not be empty");
+        }
+        if self.durability == Durability::Every(0) {
+            return invalid("durability", "every:<n> needs n of at least 1");
+        }
+        if self.compact_after == 0 {
+            return invalid("compact_after", "must be at least 1");
+        }
+        if self.timeout_ms == 0 {
+            return invalid("timeout_ms", "must be at least 1");
+        }
+        Ok(())
+    }
+
+    /// Renders the config as TOML that `ConfigLayer::from_toml` reads back.
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("config is representable as TOML")
+    }
+
+    pub fn wal_config(&self) -> WalConfig {
+        WalConfig {
+            durability: self.durability,
+            compact_after: self.compact_after,
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+
+    pub fn socket_path(&self) -> PathBuf {
+        self.socket
+            .clone()
+            .unwrap_or_else(|| self.data_dir.join("zed.sock"))
+    }
+}
+
+/// A partial `Config`, as read from one source. Unset fields leave the
+/// settings below them alone.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigLayer {
+    pub store: Option<StoreKind>,
+    pub data_dir: Option<PathBuf>,
+    #[serde(default, deserialize_with = "parsed")]
+    pub codec: Option<Format>,
+    #[serde(default, deserialize_with = "parsed")]
+    pub durability: Option<Durability>,
+    pub compact_after: Option<usize>,
+    pub timeout_ms: Option<u64>,
+    pub log_level: Option<LogLevel>,
+    pub socket: Option<PathBuf>,
+}
+
+impl ConfigLayer {
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        Self::parse_toml(text, "config".to_string())
+    }
+
+    pub fn read(path: &Path) -> Result<Self, ConfigError> {
+        let origin = path.display().to_string();
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::parse_toml(&text, origin),
+            Err(err) => Err(ConfigError::Parse {
+                origin,
+                message: err.to_string(),
+            }),
+        }
+    }
+
+    /// Parses TOML, naming `origin` in any error.
+    fn parse_toml(text: &str, origin: String) -> Result<Self, ConfigError> {
+        toml::from_str(text).map_err(|err| ConfigError::Parse {
+            origin,
+            message: err.to_string(),
+        })
+    }
+
+    /// Reads `ZED_STORE`, `ZED_DATA_DIR`, `ZED_CODEC`, `ZED_DURABILITY`,
+    /// `ZED_COMPACT_AFTER`, `ZED_TIMEOUT_MS`, `ZED_LOG_LEVEL` and
+    /// `ZED_SOCKET`.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Like `from_env`, but looks variables up with `var`.
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let get = |key: &str| {
+            let name = format!("{ENV_PREFIX}{}", key.to_uppercase());
+            var(&name).map(|value| (name, value))
+        };
+        Ok(Self {
+            store: get("store").map(choice).transpose()?,
+            data_dir: get("data_dir").map(|(_, value)| PathBuf::from(value)),
+            codec: get("codec").map(parse).transpose()?,
+            durability: get("durability").map(parse).transpose()?,
+            compact_after: get("compact_after").map(parse).transpose()?,
+            timeout_ms: get("timeout_ms").map(parse).transpose()?,
+            log_level: get("log_level").map(choice).transpose()?,
+            socket: get("socket").map(|(_, value)| PathBuf::from(value)),
+        })
+    }
+}
+
+fn parse<T>((name, value): (String, String)) -> Result<T, ConfigError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    value.parse().map_err(|err: T::Err| ConfigError::Parse {
+        origin: name,
+        message: err.to_string(),
+    })
+}
+
+fn choice<T: clap::ValueEnum>((name, value): (String, String)) -> Result<T, ConfigError> {
+    T::from_str(&value, true).map_err(|message| ConfigError::Parse {
+        origin: name,
+        message,
+    })
+}
+
+fn display<T: fmt::Display, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+fn parsed<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: FromStr<Err = String>,
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    value.parse().map(Some).map_err(serde::de::Error::custom)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// A config file or environment variable could not be read or parsed.
+    Parse { origin: String, message: String },
+    /// The merged config holds a value that is out of range.
+    Invalid { key: &'static str, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Parse { origin, message } => write!(f, "invalid {origin}: {message}"),
+            ConfigError::Invalid { key, message } => write!(f, "invalid config: {key} {message}"),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_later_layers_override_earlier_ones() {
+        let file = ConfigLayer::from_toml(
+            r#"
+            store = "wal"
+            data_dir = "/var/lib/zed"
+            durability = "every:10"
+            timeout_ms = 250
+            "#,
+        )
+        .unwrap();
+        let env = ConfigLayer::from_vars(|name| match name {
+            "ZED_TIMEOUT_MS" => Some("750".to_string()),
+            "ZED_LOG_LEVEL" => Some("DEBUG".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        let flags = ConfigLayer {
+            store: Some(StoreKind::File),
+            ..ConfigLayer::default()
+        };
+
+        let config = Config::default().merge(file).merge(env).merge(flags);
+        assert_eq!(
+            config,
+            Config {
+                store: StoreKind::File,
+                data_dir: PathBuf::from("/var/lib/zed"),
+                durability: Durability::Every(10),
+                timeout_ms: 750,
+                log_level: LogLevel::Debug,
+                ..Config::default()
+            }
+        );
+        assert_eq!(
+            Config::default().merge(ConfigLayer::from_toml(&config.to_toml()).unwrap()),
+            config
+        );
+    }
+
+    #[test]
+    fn test_errors_name_the_offending_setting() {
+        let unknown = ConfigLayer::from_toml("stor = \"file\"").unwrap_err();
+        assert!(unknown.to_string().contains("unknown field `stor`"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("zed.toml");
+        std::fs::write(&path, "timeout_ms = \"soon\"").unwrap();
+        let file = ConfigLayer::read(&path).unwrap_err();
+        assert!(file
+            .to_string()
+            .starts_with(&format!("invalid {}:", path.display())));
+
+        let env = ConfigLayer::from_vars(|name| {
+            (name == "ZED_DURABILITY").then(|| "sometimes".to_string())
+        })
+        .unwrap_err();
+        assert!(env.to_string().starts_with("invalid ZED_DURABILITY:"));
+
+        let zero = Config {
+            timeout_ms: 0,
+            ..Config::default()
+        };
+        assert_eq!(
+            zero.validate().unwrap_err().to_string(),
+            "invalid config: timeout_ms must be at least 1"
+        );
+    }
+}
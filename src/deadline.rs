@@ -0,0 +1,173 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{Foo, FooError, Zed};
+
+const PENDING: u8 = 0;
+const STARTED: u8 = 1;
+const CANCELLED: u8 = 2;
+
+/// Decides, exactly once, whether an update closure gets to run.
+#[derive(Clone, Default)]
+struct Gate(Arc<AtomicU8>);
+
+impl Gate {
+    fn enter(&self) -> bool {
+        self.0
+            .compare_exchange(PENDING, STARTED, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    fn cancel(&self) -> bool {
+        self.0
+            .compare_exchange(PENDING, CANCELLED, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+}
+
+/// Closes the gate if the call is dropped before the update started.
+struct CancelOnDrop(Gate);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+/// Deadline-bounded updates with all-or-nothing cancellation.
+///
+/// The update closure is guarded so that it only runs if it starts before
+/// the deadline. If the deadline passes first, the closure is disarmed: a
+/// store that still holds it and calls it later gets `FooError::Timeout`
+/// back and, like any rejected `try_bar`, commits nothing. If the closure
+/// had already started, the call waits for the store to finish instead, so
+/// `Ok` always means committed and `Err(Timeout)` always means not applied.
+/// Dropping the returned future disarms a closure that has not started yet.
+pub trait DeadlineFoo: Foo {
+    fn bar_with_deadline<F>(
+        &self,
+        deadline: Duration,
+        update_fn: F,
+    ) -> impl Future<Output = Result<Zed, FooError>> + Send
+    where
+        Self: Sync,
+        F: FnOnce(Zed) -> Zed + Send + 'static,
+    {
+        self.try_bar_with_deadline(deadline, move |zed| Ok(update_fn(zed)))
+    }
+
+    fn try_bar_with_deadline<F, E>(
+        &self,
+        deadline: Duration,
+        update_fn: F,
+    ) -> impl Future<Output = Result<Zed, E>> + Send
+    where
+        Self: Sync,
+        F: FnOnce(Zed) -> Result<Zed, E> + Send + 'static,
+        E: From<FooError> + Send + 'static,
+    {
+        async move {
+            let gate = Gate::default();
+            let _cancel_on_drop = CancelOnDrop(gate.clone());
+            let guard = gate.clone();
+            let call = self.try_bar(move |zed| {
+                if guard.enter() {
+                    update_fn(zed)
+                } else {
+                    Err(FooError::Timeout.into())
+                }
+            });
+            tokio::pin!(call);
+
+            match tokio::time::timeout(deadline, &mut call).await {
+                Ok(result) => result,
+                Err(_) if gate.cancel() => Err(FooError::Timeout.into()),
+                Err(_) => call.await,
+            }
+        }
+    }
+}
+
+impl<T: Foo + ?Sized> DeadlineFoo for T {}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::capture::OnceCapture;
+    use crate::{InMemoryFoo, MockFoo, TryUpdateFn};
+    use std::sync::atomic::AtomicBool;
+
+    #[tokio::test]
+    async fn test_closure_never_runs_after_timeout() {
+        let captured_update_fn = OnceCapture::<TryUpdateFn<FooError>>::new();
+        let capture = captured_update_fn.clone();
+
+        let mut mock_foo = MockFoo::new();
+        mock_foo
+            .expect_try_bar::<FooError>()
+            .times(1)
+            .returning(move |update_fn| {
+                capture.capture(update_fn);
+                Box::pin(std::future::pending())
+            });
+
+        let ran = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&ran);
+        let result = mock_foo
+            .bar_with_deadline(Duration::from_millis(10), move |zed| {
+                flag.store(true, Ordering::SeqCst);
+                zed
+            })
+            .await;
+
+        assert!(matches!(result, Err(FooError::Timeout)));
+        assert!(matches!(
+            captured_update_fn.invoke(Zed::default()),
+            Err(FooError::Timeout)
+        ));
+        assert!(!ran.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_started_update_is_seen_through() {
+        let mut mock_foo = MockFoo::new();
+        mock_foo
+            .expect_try_bar::<FooError>()
+            .times(1)
+            .returning(|update_fn| {
+                let result = update_fn(Zed::default());
+                Box::pin(async move {
+                    tokio::time::sleep(Duration::from_millis(30)).await;
+                    result
+                })
+            });
+
+        let zed = mock_foo
+            .bar_with_deadline(Duration::from_millis(5), |mut zed| {
+                zed.counter = 1;
+                zed
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(zed.counter, 1);
+    }
+
+    #[tokio::test]
+    async fn test_within_deadline() {
+        let foo = InMemoryFoo::default();
+
+        let zed = foo
+            .bar_with_deadline(Duration::from_secs(1), |mut zed| {
+                zed.counter += 1;
+                zed
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(foo.snapshot().await, zed);
+    }
+}
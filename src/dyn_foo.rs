@@ -0,0 +1,181 @@
+use std::any::Any;
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::{AsyncUpdateFn, BoxFuture, Foo, FooError, TryUpdateFn, UpdateFn, Zed};
+
+/// Rejection type carried through `DynFoo::try_bar_boxed`, where the
+/// caller's own error type has been erased.
+#[derive(Debug)]
+pub enum DynTryError {
+    Store(FooError),
+    Rejected(Rejection),
+}
+
+/// An update's own error, erased. Only the `Foo`-to-`DynFoo` bridge can
+/// create one, so recovering the original type always succeeds.
+#[derive(Debug)]
+pub struct Rejection(Box<dyn Any + Send>);
+
+impl DynTryError {
+    pub(crate) fn rejected<E: Send + 'static>(err: E) -> Self {
+        DynTryError::Rejected(Rejection(Box::new(err)))
+    }
+
+    /// Recovers the caller's error type from an erased rejection.
+    pub(crate) fn into_error<E: From<FooError> + 'static>(self) -> E {
+        match self {
+            DynTryError::Store(err) => E::from(err),
+            DynTryError::Rejected(Rejection(err)) => *err
+                .downcast::<E>()
+                .expect("DynTryError: rejection does not match the update's error type"),
+        }
+    }
+}
+
+impl From<FooError> for DynTryError {
+    fn from(err: FooError) -> Self {
+        DynTryError::Store(err)
+    }
+}
+
+/// Dyn-compatible mirror of `Foo`, so stores can be picked at runtime and
+/// held as `Arc<dyn DynFoo>`.
+///
+/// Every `Foo` gets this for free, and `dyn DynFoo` implements `Foo` in
+/// turn, so code written against `F: Foo` accepts either.
+pub trait DynFoo: Send + Sync {
+    fn get_boxed(&self) -> BoxFuture<'_, Result<Zed, FooError>>;
+
+    fn bar_boxed(&self, update_fn: UpdateFn) -> BoxFuture<'_, Result<Zed, FooError>>;
+
+    fn try_bar_boxed(
+        &self,
+        update_fn: TryUpdateFn<DynTryError>,
+    ) -> BoxFuture<'_, Result<Zed, DynTryError>>;
+
+    fn async_bar_boxed(&self, update_fn: AsyncUpdateFn) -> BoxFuture<'_, Result<Zed, FooError>>;
+}
+
+impl<T: Foo + Send + Sync> DynFoo for T {
+    fn get_boxed(&self) -> BoxFuture<'_, Result<Zed, FooError>> {
+        Box::pin(self.get())
+    }
+
+    fn bar_boxed(&self, update_fn: UpdateFn) -> BoxFuture<'_, Result<Zed, FooError>> {
+        Box::pin(self.bar(update_fn))
+    }
+
+    fn try_bar_boxed(
+        &self,
+        update_fn: TryUpdateFn<DynTryError>,
+    ) -> BoxFuture<'_, Result<Zed, DynTryError>> {
+        Box::pin(self.try_bar(update_fn))
+    }
+
+    fn async_bar_boxed(&self, update_fn: AsyncUpdateFn) -> BoxFuture<'_, Result<Zed, FooError>> {
+        Box::pin(self.async_bar(update_fn))
+    }
+}
+
+impl Foo for dyn DynFoo {
+    fn get(&self) -> impl Future<Output = Result<Zed, FooError>> + Send {
+        self.get_boxed()
+    }
+
+    fn bar<F>(&self, update_fn: F) -> impl Future<Output = Result<Zed, FooError>> + Send
+    where
+        F: FnOnce(Zed) -> Zed + Send + 'static,
+    {
+        self.bar_boxed(Box::new(update_fn))
+    }
+
+    async fn try_bar<F, E>(&self, update_fn: F) -> Result<Zed, E>
+    where
+        F: FnOnce(Zed) -> Result<Zed, E> + Send + 'static,
+        E: From<FooError> + Send + 'static,
+    {
+        let update_fn: TryUpdateFn<DynTryError> =
+            Box::new(move |zed| update_fn(zed).map_err(DynTryError::rejected));
+        self.try_bar_boxed(update_fn)
+            .await
+            .map_err(DynTryError::into_error)
+    }
+
+    fn async_bar<F>(&self, update_fn: F) -> impl Future<Output = Result<Zed, FooError>> + Send
+    where
+        F: FnOnce(Zed) -> BoxFuture<'static, Zed> + Send + 'static,
+    {
+        self.async_bar_boxed(Box::new(update_fn))
+    }
+}
+
+impl<T: Foo + ?Sized> Foo for Arc<T> {
+    fn get(&self) -> impl Future<Output = Result<Zed, FooError>> + Send {
+        (**self).get()
+    }
+
+    fn bar<F>(&self, update_fn: F) -> impl Future<Output = Result<Zed, FooError>> + Send
+    where
+        F: FnOnce(Zed) -> Zed + Send + 'static,
+    {
+        (**self).bar(update_fn)
+    }
+
+    fn try_bar<F, E>(&self, update_fn: F) -> impl Future<Output = Result<Zed, E>> + Send
+    where
+        F: FnOnce(Zed) -> Result<Zed, E> + Send + 'static,
+        E: From<FooError> + Send + 'static,
+    {
+        (**self).try_bar(update_fn)
+    }
+
+    fn async_bar<F>(&self, update_fn: F) -> impl Future<Output = Result<Zed, FooError>> + Send
+    where
+        F: FnOnce(Zed) -> BoxFuture<'static, Zed> + Send + 'static,
+    {
+        (**self).async_bar(update_fn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::capture::OnceCapture;
+    use crate::{BazError, BazImpl, InMemoryFoo, MockFoo};
+
+    #[tokio::test]
+    async fn test_baz_over_dyn_foo() {
+        let captured_update_fn = OnceCapture::<UpdateFn>::new();
+        let mut mock_foo = MockFoo::new();
+        mock_foo
+            .expect_bar()
+            .times(1)
+            .returning(captured_update_fn.returning_fn());
+
+        let stores: Vec<Arc<dyn DynFoo>> = vec![
+            Arc::new(mock_foo),
+            Arc::new(InMemoryFoo::new(Zed::builder().counter(3).build())),
+        ];
+        let mut results = Vec::new();
+        for store in stores {
+            let baz = BazImpl::new(store);
+            results.push(baz.baz().await.unwrap());
+        }
+
+        assert!(captured_update_fn.was_captured());
+        assert_eq!(results[1].counter, 3);
+    }
+
+    #[tokio::test]
+    async fn test_try_bar_keeps_rejection_type() {
+        let foo: Arc<dyn DynFoo> = Arc::new(InMemoryFoo::default());
+
+        let result = foo
+            .try_bar(|_| Err::<Zed, _>(BazError::Rejected("nope".to_string())))
+            .await;
+
+        assert!(matches!(result, Err(BazError::Rejected(reason)) if reason == "nope"));
+    }
+}
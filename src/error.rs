@@ -0,0 +1,90 @@
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+/// Errors a `Foo` store can report from `bar`.
+#[derive(Debug, Clone)]
+pub enum FooError {
+    Io(Arc<io::Error>),
+    Corrupt(String),
+    Conflict {
+        attempts: u32,
+    },
+    Closed,
+    Unavailable(String),
+    Timeout,
+    /// The update closure panicked, leaving the state as it was, or the
+    /// store panicked while applying it.
+    Panicked(String),
+}
+
+impl fmt::Display for FooError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FooError::Io(err) => write!(f, "store I/O failed: {err}"),
+            FooError::Corrupt(reason) => write!(f, "stored state is corrupt: {reason}"),
+            FooError::Conflict { attempts } => {
+                write!(
+                    f,
+                    "update conflicted with a concurrent write after {attempts} attempt(s)"
+                )
+            }
+            FooError::Closed => write!(f, "store is closed"),
+            FooError::Unavailable(reason) => write!(f, "store is unavailable: {reason}"),
+            FooError::Timeout => write!(f, "update missed its deadline and was not applied"),
+            FooError::Panicked(message) => write!(f, "update panicked: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for FooError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FooError::Io(err) => Some(err.as_ref()),
+            FooError::Corrupt(_)
+            | FooError::Conflict { .. }
+            | FooError::Closed
+            | FooError::Unavailable(_)
+            | FooError::Timeout
+            | FooError::Panicked(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for FooError {
+    fn from(err: io::Error) -> Self {
+        FooError::Io(Arc::new(err))
+    }
+}
+
+/// Errors surfaced by `BazImpl`: either the store failed or the update
+/// rejected the state it was given.
+#[derive(Debug, Clone)]
+pub enum BazError {
+    Store(FooError),
+    Rejected(String),
+}
+
+impl fmt::Display for BazError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BazError::Store(err) => err.fmt(f),
+            BazError::Rejected(reason) => write!(f, "update rejected: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for BazError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BazError::Store(err) => Some(err),
+            BazError::Rejected(_) => None,
+        }
+    }
+}
+
+impl From<FooError> for BazError {
+    fn from(err: FooError) -> Self {
+        BazError::Store(err)
+    }
+}
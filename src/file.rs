@@ -0,0 +1,295 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::codec::{Codec, TextCodec};
+use crate::{BoxFuture, Foo, FooError, Zed};
+
+/// A `Foo` that keeps its `Zed` in a file.
+///
+/// `bar` reads the current state, applies the update and writes the result
+/// to a sibling temp file that is then renamed over the original, so the
+/// file always holds either the old or the new state. A missing file reads
+/// as `Zed::default()`. The file is in the text format unless another
+/// codec is given.
+///
+/// Updates also take an exclusive lock on a sibling `.lock` file, so
+/// several processes can share one state file without losing updates.
+pub struct FileFoo {
+    path: PathBuf,
+    codec: Box<dyn Codec>,
+    lock: Mutex<()>,
+}
+
+impl FileFoo {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            codec: Box::new(TextCodec),
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn with_codec(mut self, codec: impl Codec + 'static) -> Self {
+        self.codec = Box::new(codec);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub async fn load(&self) -> Result<Zed, FooError> {
+        match fs::read(&self.path).await {
+            Ok(bytes) => self.codec.decode(&bytes),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Zed::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn store(&self, zed: &Zed) -> Result<(), FooError> {
+        write_atomic(&self.path, &self.codec.encode(zed)).await
+    }
+
+    /// Blocks until this process holds the lock file; dropping the returned
+    /// file releases it.
+    async fn lock_file(&self) -> Result<std::fs::File, FooError> {
+        let path = sibling(&self.path, ".lock");
+        let file = tokio::task::spawn_blocking(move || {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(path)?;
+            file.lock()?;
+            Ok::<_, std::io::Error>(file)
+        })
+        .await
+        .map_err(std::io::Error::other)??;
+        Ok(file)
+    }
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Writes `bytes` to a uniquely named sibling temp file, renames it over
+/// `path` and syncs the directory so the rename survives a crash. The temp
+/// file is removed if any step fails.
+pub(crate) async fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), FooError> {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let tmp = sibling(
+        path,
+        &format!(
+            ".{}.{}.tmp",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ),
+    );
+
+    let written = async {
+        let mut file = fs::File::create(&tmp).await?;
+        file.write_all(bytes).await?;
+        file.sync_all().await?;
+        drop(file);
+        fs::rename(&tmp, path).await
+    }
+    .await;
+    if let Err(err) = written {
+        let _ = fs::remove_file(&tmp).await;
+        return Err(err.into());
+    }
+
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        fs::File::open(dir).await?.sync_all().await?;
+    }
+    Ok(())
+}
+
+impl Foo for FileFoo {
+    /// Writes replace the file with a rename, so this never sees a
+    /// half-written state and needs no lock.
+    async fn get(&self) -> Result<Zed, FooError> {
+        self.load().await
+    }
+
+    async fn bar<F>(&self, update_fn: F) -> Result<Zed, FooError>
+    where
+        F: FnOnce(Zed) -> Zed + Send + 'static,
+    {
+        self.try_bar(move |zed| Ok::<_, FooError>(update_fn(zed)))
+            .await
+    }
+
+    async fn try_bar<F, E>(&self, update_fn: F) -> Result<Zed, E>
+    where
+        F: FnOnce(Zed) -> Result<Zed, E> + Send + 'static,
+        E: From<FooError> + Send + 'static,
+    {
+        let _guard = self.lock.lock().await;
+        let _lock = self.lock_file().await?;
+        let zed = update_fn(self.load().await?)?;
+        self.store(&zed).await?;
+        Ok(zed)
+    }
+
+    async fn async_bar<F>(&self, update_fn: F) -> Result<Zed, FooError>
+    where
+        F: FnOnce(Zed) -> BoxFuture<'static, Zed> + Send + 'static,
+    {
+        let _guard = self.lock.lock().await;
+        let _lock = self.lock_file().await?;
+        let zed = update_fn(self.load().await?).await;
+        self.store(&zed).await?;
+        Ok(zed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::codec::BinaryCodec;
+
+    #[tokio::test]
+    async fn test_state_survives_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("zed.state");
+
+        let foo = FileFoo::new(&path);
+        foo.bar(|_| {
+            Zed::builder()
+                .name("first\nline")
+                .entry("a\\b")
+                .version(4)
+                .build()
+        })
+        .await
+        .unwrap();
+        let zed = foo
+            .bar(|mut zed| {
+                zed.counter += 1;
+                zed
+            })
+            .await
+            .unwrap();
+
+        let reopened = FileFoo::new(&path);
+        assert_eq!(reopened.load().await.unwrap(), zed);
+        assert_eq!(zed.name, "first\nline");
+        assert_eq!(zed.entries, ["a\\b"]);
+        assert_eq!(zed.counter, 1);
+        assert_eq!(zed.version, 4);
+    }
+
+    #[tokio::test]
+    async fn test_async_update_reads_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("name.txt");
+        std::fs::write(&source, "from disk").unwrap();
+
+        let foo = FileFoo::new(dir.path().join("zed.state"));
+        let zed = foo
+            .async_bar(move |mut zed| {
+                Box::pin(async move {
+                    zed.name = fs::read_to_string(source).await.unwrap();
+                    zed
+                })
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(zed.name, "from disk");
+        assert_eq!(foo.load().await.unwrap(), zed);
+    }
+
+    #[tokio::test]
+    async fn test_binary_codec() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("zed.bin");
+
+        let foo = FileFoo::new(&path).with_codec(BinaryCodec);
+        let zed = foo
+            .bar(|_| Zed::builder().name("bin").counter(2).build())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            BinaryCodec.decode(&std::fs::read(&path).unwrap()).unwrap(),
+            zed
+        );
+        assert!(matches!(
+            FileFoo::new(&path).load().await,
+            Err(FooError::Corrupt(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_instances_on_one_path_do_not_lose_updates() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("zed.state");
+
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let foo = FileFoo::new(&path);
+                tokio::spawn(async move {
+                    for _ in 0..5 {
+                        foo.bar(|mut zed| {
+                            zed.counter += 1;
+                            zed
+                        })
+                        .await
+                        .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert_eq!(FileFoo::new(&path).load().await.unwrap().counter, 40);
+        let leftovers: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .filter(|name| name.to_string_lossy().ends_with(".tmp"))
+            .collect();
+        assert!(leftovers.is_empty(), "{leftovers:?}");
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_file_is_left_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("zed.state");
+        std::fs::write(&path, "counter many\n").unwrap();
+
+        let foo = FileFoo::new(&path);
+        let result = foo.bar(|zed| zed).await;
+
+        assert!(matches!(result, Err(FooError::Corrupt(_))));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "counter many\n");
+    }
+
+    #[tokio::test]
+    async fn test_get_does_not_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("zed.state");
+
+        let foo = FileFoo::new(&path);
+        assert_eq!(foo.get().await.unwrap(), Zed::default());
+        assert!(!path.exists());
+        assert!(!dir.path().join("zed.state.lock").exists());
+    }
+}
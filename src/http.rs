@@ -0,0 +1,88 @@
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+
+use axum::extract::rejection::JsonRejection;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_json::json;
+use tokio::net::TcpListener;
+
+use crate::{BazError, BazImpl, Foo, FooError, Operation, Zed};
+
+/// Routes of the HTTP API:
+///
+/// - `GET /zed` returns the current state.
+/// - `POST /operations` runs an `Operation` such as
+///   `{"op":"increment","by":2}` and returns the new state.
+/// - `GET /health` returns `{"status":"ok"}` while the server is up.
+///
+/// Failures are `{"error": "..."}` with 400 for a malformed body, 422 for
+/// a rejected update, 503 or 504 for an unavailable or slow store and 500
+/// otherwise.
+pub fn router<F>(baz: Arc<BazImpl<F>>) -> Router
+where
+    F: Foo + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/zed", get(show::<F>))
+        .route("/operations", post(apply::<F>))
+        .route("/health", get(health))
+        .with_state(baz)
+}
+
+/// Serves `router(baz)` on `listener` until `shutdown` resolves, then lets
+/// in-flight requests finish.
+pub async fn serve<F>(
+    listener: TcpListener,
+    baz: Arc<BazImpl<F>>,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> io::Result<()>
+where
+    F: Foo + Send + Sync + 'static,
+{
+    axum::serve(listener, router(baz))
+        .with_graceful_shutdown(shutdown)
+        .await
+}
+
+async fn show<F: Foo>(State(baz): State<Arc<BazImpl<F>>>) -> Result<Json<Zed>, BazError> {
+    Ok(Json(baz.get().await?))
+}
+
+async fn apply<F: Foo>(
+    State(baz): State<Arc<BazImpl<F>>>,
+    operation: Result<Json<Operation>, JsonRejection>,
+) -> Result<Json<Zed>, Response> {
+    let Json(operation) =
+        operation.map_err(|rejection| error(StatusCode::BAD_REQUEST, rejection.body_text()))?;
+    match baz.apply(operation).await {
+        Ok(zed) => Ok(Json(zed)),
+        Err(err) => Err(err.into_response()),
+    }
+}
+
+async fn health() -> Json<serde_json::Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+fn error(status: StatusCode, message: impl Into<String>) -> Response {
+    (status, Json(json!({ "error": message.into() }))).into_response()
+}
+
+impl IntoResponse for BazError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            BazError::Rejected(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            BazError::Store(FooError::Timeout) => StatusCode::GATEWAY_TIMEOUT,
+            BazError::Store(FooError::Unavailable(_) | FooError::Closed) => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            BazError::Store(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        error(status, self.to_string())
+    }
+}
@@ -0,0 +1,264 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{BoxFuture, ChaosFoo, ChaosPolicy, DeadlineFoo, Foo, FooError, Zed};
+
+/// Wraps a `Foo` in another `Foo`, in the spirit of `tower::Layer`.
+pub trait FooLayer<S> {
+    type Foo: Foo;
+
+    fn layer(&self, inner: S) -> Self::Foo;
+}
+
+/// Stacks layers around a store. The last layer added is the outermost.
+pub struct FooBuilder<S> {
+    inner: S,
+}
+
+impl<S: Foo> FooBuilder<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    pub fn layer<L: FooLayer<S>>(self, layer: L) -> FooBuilder<L::Foo> {
+        FooBuilder {
+            inner: layer.layer(self.inner),
+        }
+    }
+
+    pub fn build(self) -> S {
+        self.inner
+    }
+}
+
+/// Bounds `bar` and `try_bar` with `DeadlineFoo`, so a timed out update is
+/// never applied. `async_bar` is passed through unbounded.
+#[derive(Debug, Clone, Copy)]
+pub struct TimeoutLayer {
+    deadline: Duration,
+}
+
+impl TimeoutLayer {
+    pub fn new(deadline: Duration) -> Self {
+        Self { deadline }
+    }
+}
+
+impl<S: Foo + Sync> FooLayer<S> for TimeoutLayer {
+    type Foo = TimeoutFoo<S>;
+
+    fn layer(&self, inner: S) -> TimeoutFoo<S> {
+        TimeoutFoo {
+            inner,
+            deadline: self.deadline,
+        }
+    }
+}
+
+pub struct TimeoutFoo<S> {
+    inner: S,
+    deadline: Duration,
+}
+
+impl<S: Foo + Sync> Foo for TimeoutFoo<S> {
+    /// A read has nothing to apply, so it is simply abandoned at the
+    /// deadline.
+    async fn get(&self) -> Result<Zed, FooError> {
+        tokio::time::timeout(self.deadline, self.inner.get())
+            .await
+            .map_err(|_| FooError::Timeout)?
+    }
+
+    fn bar<F>(&self, update_fn: F) -> impl Future<Output = Result<Zed, FooError>> + Send
+    where
+        F: FnOnce(Zed) -> Zed + Send + 'static,
+    {
+        self.inner.bar_with_deadline(self.deadline, update_fn)
+    }
+
+    fn try_bar<F, E>(&self, update_fn: F) -> impl Future<Output = Result<Zed, E>> + Send
+    where
+        F: FnOnce(Zed) -> Result<Zed, E> + Send + 'static,
+        E: From<FooError> + Send + 'static,
+    {
+        self.inner.try_bar_with_deadline(self.deadline, update_fn)
+    }
+
+    fn async_bar<F>(&self, update_fn: F) -> impl Future<Output = Result<Zed, FooError>> + Send
+    where
+        F: FnOnce(Zed) -> BoxFuture<'static, Zed> + Send + 'static,
+    {
+        self.inner.async_bar(update_fn)
+    }
+}
+
+/// Observes each update as the store applies it. `after` is only called
+/// for updates that produced a new state.
+pub trait UpdateHook: Send + Sync + 'static {
+    fn before(&self, _zed: &Zed) {}
+
+    fn after(&self, _before: &Zed, _after: &Zed) {}
+}
+
+/// Runs an `UpdateHook` around every update closure.
+pub struct InterceptLayer<H> {
+    hook: Arc<H>,
+}
+
+impl<H: UpdateHook> InterceptLayer<H> {
+    pub fn new(hook: H) -> Self {
+        Self {
+            hook: Arc::new(hook),
+        }
+    }
+}
+
+impl<S: Foo, H: UpdateHook> FooLayer<S> for InterceptLayer<H> {
+    type Foo = InterceptFoo<S, H>;
+
+    fn layer(&self, inner: S) -> InterceptFoo<S, H> {
+        InterceptFoo {
+            inner,
+            hook: Arc::clone(&self.hook),
+        }
+    }
+}
+
+pub struct InterceptFoo<S, H> {
+    inner: S,
+    hook: Arc<H>,
+}
+
+impl<S: Foo, H: UpdateHook> Foo for InterceptFoo<S, H> {
+    fn get(&self) -> impl Future<Output = Result<Zed, FooError>> + Send {
+        self.inner.get()
+    }
+
+    fn bar<F>(&self, update_fn: F) -> impl Future<Output = Result<Zed, FooError>> + Send
+    where
+        F: FnOnce(Zed) -> Zed + Send + 'static,
+    {
+        let hook = Arc::clone(&self.hook);
+        self.inner.bar(move |zed| {
+            hook.before(&zed);
+            let after = update_fn(zed.clone());
+            hook.after(&zed, &after);
+            after
+        })
+    }
+
+    fn try_bar<F, E>(&self, update_fn: F) -> impl Future<Output = Result<Zed, E>> + Send
+    where
+        F: FnOnce(Zed) -> Result<Zed, E> + Send + 'static,
+        E: From<FooError> + Send + 'static,
+    {
+        let hook = Arc::clone(&self.hook);
+        self.inner.try_bar(move |zed| {
+            hook.before(&zed);
+            let after = update_fn(zed.clone())?;
+            hook.after(&zed, &after);
+            Ok(after)
+        })
+    }
+
+    fn async_bar<F>(&self, update_fn: F) -> impl Future<Output = Result<Zed, FooError>> + Send
+    where
+        F: FnOnce(Zed) -> BoxFuture<'static, Zed> + Send + 'static,
+    {
+        let hook = Arc::clone(&self.hook);
+        self.inner.async_bar(move |zed| {
+            Box::pin(async move {
+                hook.before(&zed);
+                let after = update_fn(zed.clone()).await;
+                hook.after(&zed, &after);
+                after
+            })
+        })
+    }
+}
+
+impl<S: Foo + Sync> FooLayer<S> for ChaosPolicy {
+    type Foo = ChaosFoo<S>;
+
+    fn layer(&self, inner: S) -> ChaosFoo<S> {
+        ChaosFoo::new(inner, self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{BazError, BazImpl, InMemoryFoo, MockFoo};
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Log(Mutex<Vec<String>>);
+
+    impl UpdateHook for Arc<Log> {
+        fn before(&self, zed: &Zed) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("before {}", zed.counter));
+        }
+
+        fn after(&self, before: &Zed, after: &Zed) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("after {} -> {}", before.counter, after.counter));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_builder_stacks_layers() {
+        let log = Arc::new(Log::default());
+        let foo = FooBuilder::new(InMemoryFoo::default())
+            .layer(InterceptLayer::new(Arc::clone(&log)))
+            .layer(TimeoutLayer::new(Duration::from_secs(1)))
+            .build();
+        let baz = BazImpl::new(foo);
+
+        baz.increment(2).await.unwrap();
+        baz.append_entry("").await.unwrap_err();
+
+        assert_eq!(
+            *log.0.lock().unwrap(),
+            ["before 0", "after 0 -> 2", "before 2"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_timeout_layer() {
+        let mut mock_foo = MockFoo::new();
+        mock_foo
+            .expect_try_bar::<BazError>()
+            .times(1)
+            .returning(|_| Box::pin(std::future::pending()));
+        let foo = FooBuilder::new(mock_foo)
+            .layer(TimeoutLayer::new(Duration::from_millis(10)))
+            .build();
+
+        let result = BazImpl::new(foo).increment(1).await;
+
+        assert!(matches!(result, Err(BazError::Store(FooError::Timeout))));
+    }
+
+    #[tokio::test]
+    async fn test_chaos_policy_is_a_layer() {
+        let policy = ChaosPolicy {
+            error_rate: 1.0,
+            ..ChaosPolicy::default()
+        };
+        let foo = FooBuilder::new(InMemoryFoo::default())
+            .layer(policy)
+            .build();
+
+        assert!(matches!(
+            foo.bar(|zed| zed).await,
+            Err(FooError::Unavailable(_))
+        ));
+    }
+}
@@ -0,0 +1,193 @@
+use mockall::automock;
+use std::future::Future;
+use std::pin::Pin;
+
+mod batch;
+mod baz;
+pub mod capture;
+mod chaos;
+pub mod codec;
+#[cfg(feature = "cli")]
+pub mod config;
+mod deadline;
+mod dyn_foo;
+mod error;
+mod file;
+#[cfg(feature = "http")]
+pub mod http;
+mod layer;
+mod memory;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+mod operation;
+mod record;
+#[cfg(all(feature = "serde", unix))]
+pub mod rpc;
+#[cfg(feature = "tracing")]
+pub mod trace;
+#[cfg(not(feature = "tracing"))]
+mod trace;
+mod versioned;
+mod wal;
+mod zed;
+
+pub use batch::{BatchConfig, Batcher};
+pub use baz::BazImpl;
+pub use chaos::{ChaosFoo, ChaosPolicy};
+pub use deadline::DeadlineFoo;
+pub use dyn_foo::{DynFoo, DynTryError, Rejection};
+pub use error::{BazError, FooError};
+pub use file::FileFoo;
+pub use layer::{
+    FooBuilder, FooLayer, InterceptFoo, InterceptLayer, TimeoutFoo, TimeoutLayer, UpdateHook,
+};
+pub use memory::InMemoryFoo;
+pub use operation::Operation;
+pub use record::{Interaction, Method, RecordingFoo, ReplayFoo};
+pub use versioned::{RetryPolicy, VersionedFoo};
+pub use wal::{Durability, WalConfig, WalFoo};
+pub use zed::{Zed, ZedBuilder};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+pub type UpdateFn = Box<dyn FnOnce(Zed) -> Zed + Send>;
+
+pub type TryUpdateFn<E> = Box<dyn FnOnce(Zed) -> Result<Zed, E> + Send>;
+
+pub type AsyncUpdateFn = Box<dyn FnOnce(Zed) -> BoxFuture<'static, Zed> + Send>;
+
+/// A store that hands its current `Zed` to an update closure and keeps the
+/// result.
+///
+/// Dropping a returned future before it resolves leaves it unspecified
+/// whether the update was applied; use `DeadlineFoo::bar_with_deadline`
+/// when the caller needs to know.
+#[automock]
+pub trait Foo {
+    /// Reads the current state without running an update.
+    fn get(&self) -> impl Future<Output = Result<Zed, FooError>> + Send;
+
+    fn bar<F>(&self, update_fn: F) -> impl Future<Output = Result<Zed, FooError>> + Send
+    where
+        F: FnOnce(Zed) -> Zed + Send + 'static;
+
+    /// Like `bar`, but the update may reject the state. On `Err` the store
+    /// keeps its current state and the error is handed back unchanged.
+    fn try_bar<F, E>(&self, update_fn: F) -> impl Future<Output = Result<Zed, E>> + Send
+    where
+        F: FnOnce(Zed) -> Result<Zed, E> + Send + 'static,
+        E: From<FooError> + Send + 'static;
+
+    /// Like `bar`, but the update may await before producing the new state.
+    ///
+    /// Whether other writers wait for the update is up to the store:
+    /// `InMemoryFoo`, `FileFoo` and `WalFoo` hold their write lock until the
+    /// returned future resolves, while `VersionedFoo` holds none and fails
+    /// with `FooError::Conflict` if another write landed in the meantime.
+    fn async_bar<F>(&self, update_fn: F) -> impl Future<Output = Result<Zed, FooError>> + Send
+    where
+        F: FnOnce(Zed) -> BoxFuture<'static, Zed> + Send + 'static;
+}
+
+/// A `Foo` that can re-run its update after losing a race with a concurrent
+/// writer, which is why the closure here is `Fn` rather than `FnOnce`.
+#[automock]
+pub trait RetryFoo {
+    fn bar_retrying<F>(&self, update_fn: F) -> impl Future<Output = Result<Zed, FooError>> + Send
+    where
+        F: Fn(Zed) -> Zed + Send + Sync + 'static;
+}
+
+pub struct FooImpl;
+
+impl Foo for FooImpl {
+    async fn get(&self) -> Result<Zed, FooError> {
+        Ok(Zed::default())
+    }
+
+    async fn bar<F>(&self, update_fn: F) -> Result<Zed, FooError>
+    where
+        F: FnOnce(Zed) -> Zed + Send + 'static,
+    {
+        Ok(update_fn(Zed::default()))
+    }
+
+    async fn try_bar<F, E>(&self, update_fn: F) -> Result<Zed, E>
+    where
+        F: FnOnce(Zed) -> Result<Zed, E> + Send + 'static,
+        E: From<FooError> + Send + 'static,
+    {
+        update_fn(Zed::default())
+    }
+
+    async fn async_bar<F>(&self, update_fn: F) -> Result<Zed, FooError>
+    where
+        F: FnOnce(Zed) -> BoxFuture<'static, Zed> + Send + 'static,
+    {
+        Ok(update_fn(Zed::default()).await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::capture::{applying, OnceCapture};
+
+    #[tokio::test]
+    async fn test_foo() {
+        let captured_update_fn = OnceCapture::<UpdateFn>::new();
+
+        let mut mock_foo = MockFoo::new();
+        mock_foo
+            .expect_bar()
+            .times(1)
+            .returning(captured_update_fn.returning_fn());
+
+        let baz = BazImpl::new(mock_foo);
+        baz.baz().await.unwrap();
+
+        assert!(captured_update_fn.was_captured());
+        let seed = Zed::builder().name("seed").counter(7).entry("a").build();
+        assert_eq!(captured_update_fn.invoke(seed.clone()), seed);
+    }
+
+    #[tokio::test]
+    async fn test_foo_returns_applied_state() {
+        let seed = Zed::builder().name("seed").counter(7).build();
+
+        let mut mock_foo = MockFoo::new();
+        mock_foo
+            .expect_bar()
+            .times(1)
+            .returning(applying(seed.clone()));
+
+        let baz = BazImpl::new(mock_foo);
+        assert_eq!(baz.baz().await.unwrap(), seed);
+    }
+
+    #[tokio::test]
+    async fn test_async_bar_captures_boxed_future() {
+        let captured_update_fn = OnceCapture::<AsyncUpdateFn>::new();
+
+        let mut mock_foo = MockFoo::new();
+        mock_foo
+            .expect_async_bar()
+            .times(1)
+            .returning(captured_update_fn.returning_fn());
+
+        mock_foo
+            .async_bar(|mut zed| {
+                Box::pin(async move {
+                    tokio::task::yield_now().await;
+                    zed.counter += 1;
+                    zed
+                })
+            })
+            .await
+            .unwrap();
+
+        let zed = captured_update_fn.invoke(Zed::default()).await;
+        assert_eq!(zed.counter, 1);
+    }
+}
@@ -1,64 +1,425 @@
-use mockall::automock;
+#[cfg(feature = "http")]
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
 
-#[tokio::main]
-async fn main() {
-    let foo = FooImpl {};
-    let baz = BazImpl {};
-    baz.baz(foo).await;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use rust_mock_challenge::codec::Format;
+use rust_mock_challenge::config::{Config, ConfigError, ConfigLayer, LogLevel, StoreKind};
+#[cfg(feature = "http")]
+use rust_mock_challenge::http;
+#[cfg(unix)]
+use rust_mock_challenge::rpc::{self, Request, RpcClient};
+use rust_mock_challenge::{
+    BazError, BazImpl, Durability, DynFoo, FileFoo, Foo, FooBuilder, FooError, InMemoryFoo,
+    Operation, TimeoutLayer, WalFoo, Zed,
+};
+
+/// Exit code for a store that failed or could not be opened.
+const EXIT_STORE: u8 = 1;
+/// Exit code for a bad command line or config; clap uses it for usage errors.
+const EXIT_CONFIG: u8 = 2;
+/// Exit code for an update that rejected the current state.
+const EXIT_REJECTED: u8 = 3;
+
+/// Inspect and update a `Zed` store.
+///
+/// Settings are taken from the defaults, then the `--config` file, then
+/// `ZED_*` environment variables, then the flags below.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    /// TOML file to read settings from.
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Print the effective settings as TOML and exit.
+    #[arg(long, global = true)]
+    print_config: bool,
+
+    /// Backing store [default: memory].
+    #[arg(long, value_enum, global = true)]
+    store: Option<StoreKind>,
+
+    /// Directory holding the file or WAL store [default: .].
+    #[arg(long, global = true)]
+    data_dir: Option<PathBuf>,
+
+    /// Encoding of the state on disk: text, binary or json [default: text].
+    #[arg(long, global = true)]
+    codec: Option<Format>,
+
+    /// When the WAL store fsyncs: always, never or every:<n> [default: always].
+    #[arg(long, global = true)]
+    durability: Option<Durability>,
+
+    /// Milliseconds an update may take before it is abandoned [default: 5000].
+    #[arg(long, global = true)]
+    timeout_ms: Option<u64>,
+
+    /// Diagnostics printed to stderr [default: warn].
+    #[arg(long, value_enum, global = true)]
+    log_level: Option<LogLevel>,
+
+    /// Socket the daemon listens on [default: <data-dir>/zed.sock].
+    #[arg(long, global = true)]
+    socket: Option<PathBuf>,
+
+    /// How to print the resulting state.
+    #[arg(long, value_enum, default_value_t = Output::Human, global = true)]
+    format: Output,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-#[cfg(test)]
-mod tests {
+impl Cli {
+    fn flags(&self) -> ConfigLayer {
+        ConfigLayer {
+            store: self.store,
+            data_dir: self.data_dir.clone(),
+            codec: self.codec,
+            durability: self.durability,
+            compact_after: None,
+            timeout_ms: self.timeout_ms,
+            log_level: self.log_level,
+            socket: self.socket.clone(),
+        }
+    }
 
-    use super::*;
-    use std::sync::{Arc, Mutex};
+    fn load_config(&self, env: ConfigLayer) -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+        if let Some(path) = &self.config {
+            config = config.merge(ConfigLayer::read(path)?);
+        }
+        let config = config.merge(env).merge(self.flags());
+        config.validate()?;
+        Ok(config)
+    }
+}
 
-    #[tokio::test]
-    async fn test_foo() {
-        type FnBox = Box<dyn FnOnce(Zed) -> Zed + Send + 'static>;
-        let captured_update_fn: Arc<Mutex<Option<FnBox>>> = Arc::new(Mutex::new(None));
-        let captured_update_fn_clone = Arc::clone(&captured_update_fn);
-
-        let mut mock_foo = MockFoo::new();
-        mock_foo
-            .expect_bar()
-            .times(1)
-            .returning(move |update_fn| {
-                let mut captured = captured_update_fn_clone.lock().unwrap();
-                *captured = Some(update_fn);
-            });
-
-        let baz = BazImpl {};
-        baz.baz(mock_foo).await;
-
-        assert!(captured_update_fn.lock().unwrap().is_some());
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Output {
+    Human,
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Print the current state.
+    Show,
+    /// Apply a named update and print the new state.
+    #[command(subcommand)]
+    Apply(Update),
+    /// Keep the store open and serve clients on the socket until stopped.
+    #[cfg(unix)]
+    Daemon,
+    /// Send a request to a running daemon and print the resulting state.
+    #[cfg(unix)]
+    #[command(subcommand)]
+    Client(ClientCommand),
+    /// Serve the HTTP API until stopped.
+    #[cfg(feature = "http")]
+    ServeHttp {
+        /// Address to listen on.
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: SocketAddr,
+    },
+}
+
+#[cfg(unix)]
+#[derive(Debug, Subcommand)]
+enum ClientCommand {
+    /// Print the daemon's current state.
+    Show,
+    /// Apply a named update in the daemon.
+    #[command(subcommand)]
+    Apply(Update),
+}
+
+#[derive(Debug, Subcommand)]
+enum Update {
+    SetName {
+        name: String,
+    },
+    Increment {
+        #[arg(default_value_t = 1)]
+        by: u64,
+    },
+    Append {
+        entry: String,
+    },
+    Reset,
+}
+
+impl From<Update> for Operation {
+    fn from(update: Update) -> Self {
+        match update {
+            Update::SetName { name } => Operation::SetName { name },
+            Update::Increment { by } => Operation::Increment { by },
+            Update::Append { entry } => Operation::AppendEntry { entry },
+            Update::Reset => Operation::Reset,
+        }
     }
 }
 
-struct Zed;
+async fn open_store(config: &Config) -> Result<Arc<dyn DynFoo>, FooError> {
+    let dir = &config.data_dir;
+    Ok(match config.store {
+        StoreKind::Memory => Arc::new(InMemoryFoo::default()),
+        StoreKind::File => {
+            Arc::new(FileFoo::new(dir.join("zed.state")).with_codec(config.codec.codec()))
+        }
+        StoreKind::Wal => {
+            Arc::new(WalFoo::open_with_codec(dir, config.wal_config(), config.codec.codec()).await?)
+        }
+    })
+}
+
+async fn open_baz(config: &Config) -> Result<BazImpl<impl Foo + Send + Sync>, FooError> {
+    let foo = FooBuilder::new(open_store(config).await?).layer(TimeoutLayer::new(config.timeout()));
+    #[cfg(feature = "tracing")]
+    let foo = foo.layer(rust_mock_challenge::trace::TracingLayer);
+    Ok(BazImpl::new(foo.build()))
+}
+
+/// Runs `command`, returning the state to print, if any.
+async fn execute(config: &Config, command: Command) -> Result<Option<Zed>, BazError> {
+    let zed = match command {
+        Command::Show => open_baz(config).await?.get().await?,
+        Command::Apply(update) => open_baz(config).await?.apply(update.into()).await?,
+        #[cfg(unix)]
+        Command::Daemon => {
+            daemon(config).await?;
+            return Ok(None);
+        }
+        #[cfg(feature = "http")]
+        Command::ServeHttp { listen } => {
+            serve_http(config, listen).await?;
+            return Ok(None);
+        }
+        #[cfg(unix)]
+        Command::Client(command) => {
+            let request = match command {
+                ClientCommand::Show => Request::Get,
+                ClientCommand::Apply(update) => Request::Apply {
+                    operation: update.into(),
+                },
+            };
+            let mut client = RpcClient::connect(config.socket_path())
+                .await
+                .map_err(FooError::from)?;
+            client
+                .call(&request)
+                .await
+                .map_err(FooError::from)?
+                .into_result()?
+        }
+    };
+    Ok(Some(zed))
+}
+
+#[cfg(unix)]
+async fn daemon(config: &Config) -> Result<(), FooError> {
+    let baz = Arc::new(open_baz(config).await?);
+    let path = config.socket_path();
+    let listener = rpc::bind(&path).await?;
+    eprintln!("listening on {}", path.display());
 
-#[automock]
-trait Foo {
-    async fn bar<F>(&self, update_fn: F)
-    where
-        F: FnOnce(Zed) -> Zed + Send + 'static;
+    let served = rpc::serve(listener, baz, shutdown_signal()).await;
+    let _ = std::fs::remove_file(&path);
+    Ok(served?)
 }
 
-struct FooImpl;
+#[cfg(feature = "http")]
+async fn serve_http(config: &Config, listen: SocketAddr) -> Result<(), FooError> {
+    let baz = Arc::new(open_baz(config).await?);
+    let listener = tokio::net::TcpListener::bind(listen).await?;
+    eprintln!("listening on http://{}", listener.local_addr()?);
+    Ok(http::serve(listener, baz, shutdown_signal()).await?)
+}
 
-impl Foo for FooImpl {
-    async fn bar<F>(&self, update_fn: F)
-    where
-        F: FnOnce(Zed) -> Zed + Send + 'static,
+/// Resolves on Ctrl-C, or on SIGTERM where there is one.
+async fn shutdown_signal() {
+    #[cfg(unix)]
     {
-        update_fn(Zed {});
+        use tokio::signal::unix::{signal, SignalKind};
+
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            return;
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+#[cfg(feature = "tracing")]
+fn init_logging(level: LogLevel) {
+    use tracing_subscriber::filter::LevelFilter;
+    use tracing_subscriber::fmt::format::FmtSpan;
+
+    let level = match level {
+        LogLevel::Off => LevelFilter::OFF,
+        LogLevel::Error => LevelFilter::ERROR,
+        LogLevel::Warn => LevelFilter::WARN,
+        LogLevel::Info => LevelFilter::INFO,
+        LogLevel::Debug => LevelFilter::DEBUG,
+        LogLevel::Trace => LevelFilter::TRACE,
+    };
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_writer(std::io::stderr)
+        .init();
+}
+
+/// Without `tracing` there is nothing to filter, so only say that the
+/// setting is being ignored.
+#[cfg(not(feature = "tracing"))]
+fn init_logging(level: LogLevel) {
+    if level != Config::default().log_level {
+        let level = level.to_possible_value().expect("no skipped levels");
+        eprintln!(
+            "warning: log level {} has no effect; this build lacks the `tracing` feature",
+            level.get_name()
+        );
+    }
+}
+
+fn print_human(zed: &Zed) {
+    println!("name:    {}", zed.name);
+    println!("counter: {}", zed.counter);
+    println!("version: {}", zed.version);
+    println!("entries:");
+    for entry in &zed.entries {
+        println!("  - {entry}");
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let config = match ConfigLayer::from_env().and_then(|env| cli.load_config(env)) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("error: {err}");
+            return ExitCode::from(EXIT_CONFIG);
+        }
+    };
+    if cli.print_config {
+        print!("{}", config.to_toml());
+        return ExitCode::SUCCESS;
+    }
+    let Some(command) = cli.command else {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::MissingSubcommand,
+                "a subcommand is required unless --print-config is given",
+            )
+            .exit();
+    };
+    init_logging(config.log_level);
+
+    match execute(&config, command).await {
+        Ok(zed) => {
+            match (zed, cli.format) {
+                (Some(zed), Output::Human) => print_human(&zed),
+                (Some(zed), Output::Json) => {
+                    println!("{}", serde_json::to_string_pretty(&zed).unwrap())
+                }
+                (None, _) => {}
+            }
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::from(match err {
+                BazError::Store(_) => EXIT_STORE,
+                BazError::Rejected(_) => EXIT_REJECTED,
+            })
+        }
     }
 }
 
-struct BazImpl;
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn cli(args: &[&str]) -> Cli {
+        Cli::try_parse_from(std::iter::once("zed").chain(args.iter().copied())).unwrap()
+    }
+
+    async fn run(args: &[&str]) -> Result<Zed, BazError> {
+        let cli = cli(args);
+        let config = cli.load_config(ConfigLayer::default()).unwrap();
+        execute(&config, cli.command.unwrap())
+            .await
+            .map(Option::unwrap)
+    }
+
+    #[tokio::test]
+    async fn test_file_store_keeps_applied_updates() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().to_str().unwrap();
+        let store = ["--store", "file", "--data-dir", data_dir];
+        let args = |command: &[&'static str]| [&store[..], command].concat();
+
+        run(&args(&["apply", "increment", "4"])).await.unwrap();
+        run(&args(&["apply", "append", "a"])).await.unwrap();
+        let zed = run(&args(&["show"])).await.unwrap();
+        assert_eq!(zed, Zed::builder().counter(4).entry("a").build());
+
+        let rejected = run(&args(&["apply", "append", ""])).await;
+        assert!(matches!(rejected, Err(BazError::Rejected(_))));
+    }
+
+    #[test]
+    fn test_parses_global_options_after_the_subcommand() {
+        let parsed = cli(&[
+            "apply", "set-name", "x", "--format", "json", "--codec", "binary",
+        ]);
+        assert_eq!(parsed.format, Output::Json);
+        assert_eq!(parsed.codec, Some(Format::Binary));
+        assert_eq!(parsed.store, None);
+        assert!(Cli::try_parse_from(["zed", "--store", "redis", "show"]).is_err());
+    }
+
+    #[test]
+    fn test_flags_override_config_file_and_env() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("zed.toml");
+        std::fs::write(
+            &path,
+            "store = \"wal\"\ntimeout_ms = 100\ncompact_after = 10\n",
+        )
+        .unwrap();
+        let env = ConfigLayer {
+            timeout_ms: Some(200),
+            ..ConfigLayer::default()
+        };
+
+        let parsed = cli(&["--config", path.to_str().unwrap(), "--print-config"]);
+        let config = parsed.load_config(env.clone()).unwrap();
+        assert_eq!(config.store, StoreKind::Wal);
+        assert_eq!(config.timeout_ms, 200);
+        assert_eq!(config.compact_after, 10);
 
-impl BazImpl {
-    async fn baz<F: Foo>(self, f: F) {
-        f.bar(|zed| zed).await;
+        let parsed = cli(&[
+            "--config",
+            path.to_str().unwrap(),
+            "--timeout-ms",
+            "0",
+            "show",
+        ]);
+        let err = parsed.load_config(env).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid config: timeout_ms must be at least 1"
+        );
     }
 }
@@ -0,0 +1,109 @@
+use tokio::sync::Mutex;
+
+use crate::{BoxFuture, Foo, FooError, Zed};
+
+/// A `Foo` that keeps its `Zed` in memory.
+///
+/// Each `bar` runs against the state left by the previous one, and calls
+/// are serialised by an async lock so concurrent updates are never lost.
+#[derive(Default)]
+pub struct InMemoryFoo {
+    state: Mutex<Zed>,
+}
+
+impl InMemoryFoo {
+    pub fn new(zed: Zed) -> Self {
+        Self {
+            state: Mutex::new(zed),
+        }
+    }
+
+    pub async fn snapshot(&self) -> Zed {
+        self.state.lock().await.clone()
+    }
+}
+
+impl Foo for InMemoryFoo {
+    async fn get(&self) -> Result<Zed, FooError> {
+        Ok(self.snapshot().await)
+    }
+
+    async fn bar<F>(&self, update_fn: F) -> Result<Zed, FooError>
+    where
+        F: FnOnce(Zed) -> Zed + Send + 'static,
+    {
+        self.try_bar(move |zed| Ok::<_, FooError>(update_fn(zed)))
+            .await
+    }
+
+    async fn try_bar<F, E>(&self, update_fn: F) -> Result<Zed, E>
+    where
+        F: FnOnce(Zed) -> Result<Zed, E> + Send + 'static,
+        E: From<FooError> + Send + 'static,
+    {
+        let mut state = self.state.lock().await;
+        let zed = update_fn(state.clone())?;
+        *state = zed.clone();
+        Ok(zed)
+    }
+
+    async fn async_bar<F>(&self, update_fn: F) -> Result<Zed, FooError>
+    where
+        F: FnOnce(Zed) -> BoxFuture<'static, Zed> + Send + 'static,
+    {
+        let mut state = self.state.lock().await;
+        let zed = update_fn(state.clone()).await;
+        *state = zed.clone();
+        Ok(zed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::BazError;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_updates_accumulate() {
+        let foo = Arc::new(InMemoryFoo::new(Zed::builder().name("seed").build()));
+
+        let tasks: Vec<_> = (0..16)
+            .map(|_| {
+                let foo = Arc::clone(&foo);
+                tokio::spawn(async move {
+                    foo.bar(|mut zed| {
+                        zed.counter += 1;
+                        zed
+                    })
+                    .await
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+
+        assert_eq!(
+            foo.snapshot().await,
+            Zed::builder().name("seed").counter(16).build()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejected_update_keeps_state() {
+        let seed = Zed::builder().name("seed").build();
+        let foo = InMemoryFoo::new(seed.clone());
+
+        let result = foo
+            .try_bar(|mut zed| {
+                zed.name.clear();
+                Err::<Zed, _>(BazError::Rejected("empty name".to_string()))
+            })
+            .await;
+
+        assert!(matches!(result, Err(BazError::Rejected(_))));
+        assert_eq!(foo.snapshot().await, seed);
+    }
+}
@@ -0,0 +1,462 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::{BazError, BoxFuture, Foo, FooError, FooLayer, Zed};
+
+/// Upper bounds, in seconds, of the histogram buckets.
+pub const DURATION_BUCKETS: [f64; 10] =
+    [0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+type Labels = Vec<(&'static str, String)>;
+
+enum Series {
+    Counter(Counter),
+    Histogram(Histogram),
+}
+
+#[derive(Default)]
+struct Families {
+    help: BTreeMap<&'static str, &'static str>,
+    series: BTreeMap<&'static str, BTreeMap<Labels, Series>>,
+}
+
+/// An in-process set of counters and histograms.
+///
+/// Clones share the same metrics. A metric is created the first time it is
+/// asked for and identified by its name and label values, so asking again
+/// returns a handle to the same series.
+#[derive(Clone, Default)]
+pub struct Registry {
+    families: Arc<Mutex<Families>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the `# HELP` text rendered for `name`.
+    pub fn describe(&self, name: &'static str, help: &'static str) {
+        self.families.lock().unwrap().help.insert(name, help);
+    }
+
+    /// # Panics
+    ///
+    /// If `name` is already registered as a histogram.
+    pub fn counter(&self, name: &'static str, labels: &[(&'static str, &str)]) -> Counter {
+        let mut families = self.families.lock().unwrap();
+        // Every series under a name has the same kind, so the first one
+        // stands for the whole family.
+        let family = families.series.entry(name).or_default();
+        if let Some(Series::Histogram(_)) = family.values().next() {
+            panic!("metric {name} is a histogram, not a counter");
+        }
+        match family
+            .entry(owned(labels))
+            .or_insert_with(|| Series::Counter(Counter::default()))
+        {
+            Series::Counter(counter) => counter.clone(),
+            Series::Histogram(_) => unreachable!("checked above"),
+        }
+    }
+
+    /// # Panics
+    ///
+    /// If `name` is already registered as a counter.
+    pub fn histogram(&self, name: &'static str, labels: &[(&'static str, &str)]) -> Histogram {
+        let mut families = self.families.lock().unwrap();
+        let family = families.series.entry(name).or_default();
+        if let Some(Series::Counter(_)) = family.values().next() {
+            panic!("metric {name} is a counter, not a histogram");
+        }
+        match family
+            .entry(owned(labels))
+            .or_insert_with(|| Series::Histogram(Histogram::default()))
+        {
+            Series::Histogram(histogram) => histogram.clone(),
+            Series::Counter(_) => unreachable!("checked above"),
+        }
+    }
+
+    /// Renders every metric in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let families = self.families.lock().unwrap();
+        let mut out = String::new();
+        for (name, series) in &families.series {
+            let Some(first) = series.values().next() else {
+                continue;
+            };
+            if let Some(help) = families.help.get(name) {
+                let _ = writeln!(
+                    out,
+                    "# HELP {name} {}",
+                    help.replace('\\', "\\\\").replace('\n', "\\n")
+                );
+            }
+            let kind = match first {
+                Series::Counter(_) => "counter",
+                Series::Histogram(_) => "histogram",
+            };
+            let _ = writeln!(out, "# TYPE {name} {kind}");
+            for (labels, series) in series {
+                match series {
+                    Series::Counter(counter) => {
+                        let _ = writeln!(
+                            out,
+                            "{name}{} {}",
+                            render_labels(labels, None),
+                            counter.get()
+                        );
+                    }
+                    Series::Histogram(histogram) => histogram.render(&mut out, name, labels),
+                }
+            }
+        }
+        out
+    }
+}
+
+fn owned(labels: &[(&'static str, &str)]) -> Labels {
+    let mut labels: Labels = labels
+        .iter()
+        .map(|(key, value)| (*key, value.to_string()))
+        .collect();
+    labels.sort();
+    labels
+}
+
+fn render_labels(labels: &Labels, le: Option<&str>) -> String {
+    let pairs: Vec<String> = labels
+        .iter()
+        .map(|(key, value)| (*key, value.as_str()))
+        .chain(le.map(|le| ("le", le)))
+        .map(|(key, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{key}=\"{value}\"")
+        })
+        .collect();
+    if pairs.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", pairs.join(","))
+    }
+}
+
+/// A monotonically increasing count.
+#[derive(Clone, Default)]
+pub struct Counter(Arc<AtomicU64>);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.inc_by(1);
+    }
+
+    pub fn inc_by(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Default)]
+struct Buckets {
+    counts: [u64; DURATION_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+/// Counts observations into the fixed `DURATION_BUCKETS`.
+#[derive(Clone, Default)]
+pub struct Histogram(Arc<Mutex<Buckets>>);
+
+impl Histogram {
+    pub fn observe(&self, value: f64) {
+        let mut buckets = self.0.lock().unwrap();
+        if let Some(i) = DURATION_BUCKETS.iter().position(|le| value <= *le) {
+            buckets.counts[i] += 1;
+        }
+        buckets.count += 1;
+        buckets.sum += value;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.0.lock().unwrap().count
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.0.lock().unwrap().sum
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &Labels) {
+        let buckets = self.0.lock().unwrap();
+        let mut cumulative = 0;
+        for (le, count) in DURATION_BUCKETS.iter().zip(buckets.counts) {
+            cumulative += count;
+            let le = le.to_string();
+            let _ = writeln!(
+                out,
+                "{name}_bucket{} {cumulative}",
+                render_labels(labels, Some(&le))
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{name}_bucket{} {}",
+            render_labels(labels, Some("+Inf")),
+            buckets.count
+        );
+        let _ = writeln!(
+            out,
+            "{name}_sum{} {}",
+            render_labels(labels, None),
+            buckets.sum
+        );
+        let _ = writeln!(
+            out,
+            "{name}_count{} {}",
+            render_labels(labels, None),
+            buckets.count
+        );
+    }
+}
+
+/// Sets the `# HELP` text of the families `operation` records.
+pub(crate) fn describe_operations(registry: &Registry) {
+    registry.describe(
+        "baz_operations_total",
+        "BazImpl operations by name and outcome.",
+    );
+    registry.describe(
+        "baz_operation_duration_seconds",
+        "Time spent in BazImpl operations.",
+    );
+}
+
+/// Records `baz_operations_total` and `baz_operation_duration_seconds` for
+/// one `BazImpl` operation.
+pub(crate) async fn operation<O>(
+    registry: &Registry,
+    name: &'static str,
+    op: O,
+) -> Result<Zed, BazError>
+where
+    O: Future<Output = Result<Zed, BazError>>,
+{
+    let started = Instant::now();
+    let result = op.await;
+    let outcome = match &result {
+        Ok(_) => "ok",
+        Err(BazError::Rejected(_)) => "rejected",
+        Err(BazError::Store(_)) => "error",
+    };
+    registry
+        .histogram("baz_operation_duration_seconds", &[("operation", name)])
+        .observe(started.elapsed().as_secs_f64());
+    registry
+        .counter(
+            "baz_operations_total",
+            &[("operation", name), ("outcome", outcome)],
+        )
+        .inc();
+    result
+}
+
+/// Wraps a store in `MeteredFoo`.
+#[derive(Clone)]
+pub struct MetricsLayer {
+    registry: Registry,
+}
+
+impl MetricsLayer {
+    pub fn new(registry: Registry) -> Self {
+        registry.describe("foo_calls_total", "Foo calls by method and outcome.");
+        registry.describe(
+            "foo_call_duration_seconds",
+            "Time spent in Foo calls, including the update closure.",
+        );
+        Self { registry }
+    }
+}
+
+impl<S: Foo + Sync> FooLayer<S> for MetricsLayer {
+    type Foo = MeteredFoo<S>;
+
+    fn layer(&self, inner: S) -> MeteredFoo<S> {
+        MeteredFoo {
+            inner,
+            registry: self.registry.clone(),
+        }
+    }
+}
+
+/// A `Foo` decorator that counts every call in `foo_calls_total` by
+/// `method` and `outcome` (`ok`, `rejected` or `error`) and times it in
+/// `foo_call_duration_seconds`.
+pub struct MeteredFoo<S> {
+    inner: S,
+    registry: Registry,
+}
+
+impl<S> MeteredFoo<S> {
+    async fn metered<T, E, C>(
+        &self,
+        method: &'static str,
+        rejected: Arc<AtomicBool>,
+        call: C,
+    ) -> Result<T, E>
+    where
+        C: Future<Output = Result<T, E>>,
+    {
+        let started = Instant::now();
+        let result = call.await;
+        let outcome = match &result {
+            Ok(_) => "ok",
+            Err(_) if rejected.load(Ordering::Relaxed) => "rejected",
+            Err(_) => "error",
+        };
+        self.registry
+            .histogram("foo_call_duration_seconds", &[("method", method)])
+            .observe(started.elapsed().as_secs_f64());
+        self.registry
+            .counter(
+                "foo_calls_total",
+                &[("method", method), ("outcome", outcome)],
+            )
+            .inc();
+        result
+    }
+}
+
+impl<S: Foo + Sync> Foo for MeteredFoo<S> {
+    fn get(&self) -> impl Future<Output = Result<Zed, FooError>> + Send {
+        self.metered("get", Arc::default(), self.inner.get())
+    }
+
+    fn bar<F>(&self, update_fn: F) -> impl Future<Output = Result<Zed, FooError>> + Send
+    where
+        F: FnOnce(Zed) -> Zed + Send + 'static,
+    {
+        self.metered("bar", Arc::default(), self.inner.bar(update_fn))
+    }
+
+    fn try_bar<F, E>(&self, update_fn: F) -> impl Future<Output = Result<Zed, E>> + Send
+    where
+        F: FnOnce(Zed) -> Result<Zed, E> + Send + 'static,
+        E: From<FooError> + Send + 'static,
+    {
+        let rejected = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&rejected);
+        let call = self.inner.try_bar(move |zed| {
+            let result = update_fn(zed);
+            flag.store(result.is_err(), Ordering::Relaxed);
+            result
+        });
+        self.metered("try_bar", rejected, call)
+    }
+
+    fn async_bar<F>(&self, update_fn: F) -> impl Future<Output = Result<Zed, FooError>> + Send
+    where
+        F: FnOnce(Zed) -> BoxFuture<'static, Zed> + Send + 'static,
+    {
+        self.metered("async_bar", Arc::default(), self.inner.async_bar(update_fn))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{BazImpl, FooBuilder, InMemoryFoo};
+
+    #[tokio::test]
+    async fn test_records_foo_calls_and_baz_operations() {
+        let registry = Registry::new();
+        let foo = FooBuilder::new(InMemoryFoo::default())
+            .layer(MetricsLayer::new(registry.clone()))
+            .build();
+        let baz = BazImpl::new(foo).with_metrics(registry.clone());
+
+        baz.increment(1).await.unwrap();
+        baz.increment(u64::MAX).await.unwrap_err();
+        baz.set_name("metered").await.unwrap();
+
+        let calls = |method, outcome| {
+            registry
+                .counter(
+                    "foo_calls_total",
+                    &[("method", method), ("outcome", outcome)],
+                )
+                .get()
+        };
+        assert_eq!(calls("try_bar", "ok"), 1);
+        assert_eq!(calls("try_bar", "rejected"), 1);
+        assert_eq!(calls("bar", "ok"), 1);
+        assert_eq!(
+            registry
+                .histogram("foo_call_duration_seconds", &[("method", "try_bar")])
+                .count(),
+            2
+        );
+
+        let operations = |operation, outcome| {
+            registry
+                .counter(
+                    "baz_operations_total",
+                    &[("outcome", outcome), ("operation", operation)],
+                )
+                .get()
+        };
+        assert_eq!(operations("increment", "ok"), 1);
+        assert_eq!(operations("increment", "rejected"), 1);
+        assert_eq!(operations("set_name", "ok"), 1);
+        assert!(registry
+            .render()
+            .contains("# HELP baz_operations_total BazImpl operations by name and outcome.\n"));
+    }
+
+    #[test]
+    fn test_renders_prometheus_text() {
+        let registry = Registry::new();
+        registry.describe("requests_total", "Requests served.");
+        registry.describe("errors_total", "Errors under C:\\temp\nby path.");
+        registry.counter("errors_total", &[]).inc();
+        registry
+            .counter("requests_total", &[("path", "/a \"b\"")])
+            .inc_by(3);
+        let histogram = registry.histogram("latency_seconds", &[]);
+        histogram.observe(0.002);
+        histogram.observe(10.0);
+
+        let text = registry.render();
+        assert!(text.contains(
+            "# HELP requests_total Requests served.\n\
+             # TYPE requests_total counter\n\
+             requests_total{path=\"/a \\\"b\\\"\"} 3\n"
+        ));
+        assert!(text.contains("# HELP errors_total Errors under C:\\\\temp\\nby path.\n"));
+        assert!(text.contains("# TYPE latency_seconds histogram\n"));
+        assert!(text.contains("latency_seconds_bucket{le=\"0.001\"} 0\n"));
+        assert!(text.contains("latency_seconds_bucket{le=\"0.005\"} 1\n"));
+        assert!(text.contains("latency_seconds_bucket{le=\"5\"} 1\n"));
+        assert!(text.contains("latency_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(text.contains("latency_seconds_sum 10.002\n"));
+        assert!(text.contains("latency_seconds_count 2\n"));
+    }
+
+    #[test]
+    #[should_panic(expected = "metric requests_total is a counter, not a histogram")]
+    fn test_kind_is_checked_across_label_values() {
+        let registry = Registry::new();
+        registry.counter("requests_total", &[("path", "/a")]);
+        registry.histogram("requests_total", &[("path", "/b")]);
+    }
+}
@@ -0,0 +1,74 @@
+use crate::{BazError, BazImpl, Foo, Zed};
+
+/// A named `BazImpl` operation, as data, for callers outside the process.
+///
+/// With the `serde` feature it is tagged by `op`, e.g.
+/// `{"op":"increment","by":2}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "op", rename_all = "snake_case"))]
+pub enum Operation {
+    SetName { name: String },
+    Increment { by: u64 },
+    AppendEntry { entry: String },
+    Reset,
+}
+
+impl<F: Foo> BazImpl<F> {
+    pub async fn apply(&self, operation: Operation) -> Result<Zed, BazError> {
+        match operation {
+            Operation::SetName { name } => self.set_name(name).await,
+            Operation::Increment { by } => self.increment(by).await,
+            Operation::AppendEntry { entry } => self.append_entry(entry).await,
+            Operation::Reset => self.reset().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::InMemoryFoo;
+
+    #[tokio::test]
+    async fn test_apply_runs_each_operation() {
+        let baz = BazImpl::new(InMemoryFoo::default());
+
+        baz.apply(Operation::SetName {
+            name: "applied".to_string(),
+        })
+        .await
+        .unwrap();
+        baz.apply(Operation::Increment { by: 3 }).await.unwrap();
+        let zed = baz
+            .apply(Operation::AppendEntry {
+                entry: "a".to_string(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            zed,
+            Zed::builder().name("applied").counter(3).entry("a").build()
+        );
+
+        let rejected = baz
+            .apply(Operation::AppendEntry {
+                entry: String::new(),
+            })
+            .await;
+        assert!(matches!(rejected, Err(BazError::Rejected(_))));
+        assert_eq!(baz.apply(Operation::Reset).await.unwrap(), Zed::default());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_json_is_tagged_by_op() {
+        let operation: Operation = serde_json::from_str(r#"{"op":"increment","by":2}"#).unwrap();
+        assert_eq!(operation, Operation::Increment { by: 2 });
+        assert_eq!(
+            serde_json::to_string(&Operation::Reset).unwrap(),
+            r#"{"op":"reset"}"#
+        );
+    }
+}
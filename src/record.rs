@@ -0,0 +1,462 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{BoxFuture, Foo, FooError, Zed};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Method {
+    Get,
+    Bar,
+    TryBar,
+    AsyncBar,
+}
+
+/// One call observed by `RecordingFoo`. `output` is `None` when a `try_bar`
+/// update rejected its input; a `get` records the state it read as both.
+///
+/// `error` holds the message of the store error the call failed with. A
+/// store that failed before running the update leaves `input` at its
+/// default and `output` at `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Interaction {
+    pub tag: String,
+    pub method: Method,
+    pub timestamp_ms: u64,
+    pub input: Zed,
+    pub output: Option<Zed>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub error: Option<String>,
+}
+
+impl Interaction {
+    /// Whether the store got as far as running the update closure.
+    fn ran_update(&self) -> bool {
+        self.error.is_none() || self.output.is_some()
+    }
+
+    /// The recorded store error, as `FooError::Unavailable`.
+    fn failure(&self) -> Result<(), FooError> {
+        match &self.error {
+            Some(message) => Err(FooError::Unavailable(message.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The input and output of an update closure, held until the store call
+/// they belong to resolves.
+type Seen = Arc<Mutex<Option<(Zed, Option<Zed>)>>>;
+
+/// Lets `RecordingFoo::try_bar` tell a store error from the caller's own.
+enum TryBarError<E> {
+    Rejected(E),
+    Store(FooError),
+}
+
+impl<E> From<FooError> for TryBarError<E> {
+    fn from(err: FooError) -> Self {
+        TryBarError::Store(err)
+    }
+}
+
+#[derive(Clone)]
+struct Recorder {
+    trace: Arc<Mutex<Vec<Interaction>>>,
+    tag: Arc<str>,
+}
+
+impl Recorder {
+    fn push(&self, method: Method, input: Zed, output: Option<Zed>, error: Option<&FooError>) {
+        self.trace.lock().unwrap().push(Interaction {
+            tag: self.tag.to_string(),
+            method,
+            timestamp_ms: now_ms(),
+            input,
+            output,
+            error: error.map(ToString::to_string),
+        });
+    }
+
+    /// Records an update once the store has answered, so a failed commit
+    /// is not logged as if it went through.
+    fn finish(&self, method: Method, seen: &Seen, error: Option<&FooError>) {
+        let (input, output) = seen.lock().unwrap().take().unwrap_or_default();
+        self.push(method, input, output, error);
+    }
+}
+
+/// A `Foo` decorator that logs every read and update it forwards to the
+/// inner store.
+///
+/// Handles made with `with_tag` share the store and the trace, so each
+/// caller can be told apart in the log.
+pub struct RecordingFoo<F> {
+    inner: Arc<F>,
+    recorder: Recorder,
+}
+
+impl<F> RecordingFoo<F> {
+    pub fn new(inner: F) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            recorder: Recorder {
+                trace: Arc::default(),
+                tag: Arc::from(""),
+            },
+        }
+    }
+
+    pub fn with_tag(&self, tag: impl Into<Arc<str>>) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+            recorder: Recorder {
+                trace: Arc::clone(&self.recorder.trace),
+                tag: tag.into(),
+            },
+        }
+    }
+
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    pub fn trace(&self) -> Vec<Interaction> {
+        self.recorder.trace.lock().unwrap().clone()
+    }
+
+    /// Writes the trace recorded so far to `path` as JSON.
+    #[cfg(feature = "serde")]
+    pub async fn dump(&self, path: impl AsRef<std::path::Path>) -> Result<(), FooError> {
+        let json = serde_json::to_vec_pretty(&self.trace())
+            .map_err(|err| FooError::Corrupt(err.to_string()))?;
+        tokio::fs::write(path, json).await?;
+        Ok(())
+    }
+}
+
+impl<F: Foo> Foo for RecordingFoo<F> {
+    fn get(&self) -> impl Future<Output = Result<Zed, FooError>> + Send {
+        let recorder = self.recorder.clone();
+        let call = self.inner.get();
+        async move {
+            let result = call.await;
+            match &result {
+                Ok(zed) => recorder.push(Method::Get, zed.clone(), Some(zed.clone()), None),
+                Err(err) => recorder.push(Method::Get, Zed::default(), None, Some(err)),
+            }
+            result
+        }
+    }
+
+    fn bar<U>(&self, update_fn: U) -> impl Future<Output = Result<Zed, FooError>> + Send
+    where
+        U: FnOnce(Zed) -> Zed + Send + 'static,
+    {
+        let recorder = self.recorder.clone();
+        let seen = Seen::default();
+        let record = Arc::clone(&seen);
+        let call = self.inner.bar(move |zed| {
+            let output = update_fn(zed.clone());
+            *record.lock().unwrap() = Some((zed, Some(output.clone())));
+            output
+        });
+        async move {
+            let result = call.await;
+            recorder.finish(Method::Bar, &seen, result.as_ref().err());
+            result
+        }
+    }
+
+    fn try_bar<U, E>(&self, update_fn: U) -> impl Future<Output = Result<Zed, E>> + Send
+    where
+        U: FnOnce(Zed) -> Result<Zed, E> + Send + 'static,
+        E: From<FooError> + Send + 'static,
+    {
+        let recorder = self.recorder.clone();
+        let seen = Seen::default();
+        let record = Arc::clone(&seen);
+        let call = self.inner.try_bar(move |zed| {
+            let output = update_fn(zed.clone());
+            *record.lock().unwrap() = Some((zed, output.as_ref().ok().cloned()));
+            output.map_err(TryBarError::Rejected)
+        });
+        async move {
+            let result = call.await;
+            let error = match &result {
+                Err(TryBarError::Store(err)) => Some(err),
+                _ => None,
+            };
+            recorder.finish(Method::TryBar, &seen, error);
+            result.map_err(|err| match err {
+                TryBarError::Rejected(err) => err,
+                TryBarError::Store(err) => err.into(),
+            })
+        }
+    }
+
+    fn async_bar<U>(&self, update_fn: U) -> impl Future<Output = Result<Zed, FooError>> + Send
+    where
+        U: FnOnce(Zed) -> BoxFuture<'static, Zed> + Send + 'static,
+    {
+        let recorder = self.recorder.clone();
+        let seen = Seen::default();
+        let record = Arc::clone(&seen);
+        let call = self.inner.async_bar(move |zed| {
+            Box::pin(async move {
+                let output = update_fn(zed.clone()).await;
+                *record.lock().unwrap() = Some((zed, Some(output.clone())));
+                output
+            })
+        });
+        async move {
+            let result = call.await;
+            recorder.finish(Method::AsyncBar, &seen, result.as_ref().err());
+            result
+        }
+    }
+}
+
+/// A `Foo` that plays back a recorded trace instead of holding state.
+///
+/// Each call takes the next `Interaction`, feeds its `input` to the update
+/// closure and panics if the method or the closure's output differs from
+/// what was recorded; a `get` just returns the recorded state. A call
+/// recorded with a store error fails with `FooError::Unavailable`, after
+/// running its update if the store had run it. Call `assert_finished` at
+/// the end of the test to check the whole trace was consumed.
+pub struct ReplayFoo {
+    expected: Mutex<VecDeque<Interaction>>,
+    calls: AtomicUsize,
+}
+
+impl ReplayFoo {
+    pub fn new(trace: Vec<Interaction>) -> Self {
+        Self {
+            expected: Mutex::new(trace.into()),
+            calls: AtomicUsize::new(0),
+        }
+    }
+
+    /// Reads a trace written by `RecordingFoo::dump`.
+    #[cfg(feature = "serde")]
+    pub async fn load(path: impl AsRef<std::path::Path>) -> Result<Self, FooError> {
+        let json = tokio::fs::read(path).await?;
+        let trace =
+            serde_json::from_slice(&json).map_err(|err| FooError::Corrupt(err.to_string()))?;
+        Ok(Self::new(trace))
+    }
+
+    #[track_caller]
+    pub fn assert_finished(&self) {
+        let remaining = self.expected.lock().unwrap().len();
+        assert_eq!(
+            remaining, 0,
+            "ReplayFoo: {remaining} recorded interaction(s) were never replayed"
+        );
+    }
+
+    fn next(&self, method: Method) -> (usize, Interaction) {
+        let call = self.calls.fetch_add(1, Ordering::Relaxed);
+        let Some(expected) = self.expected.lock().unwrap().pop_front() else {
+            panic!("ReplayFoo: unexpected call #{call} to {method:?}, the trace is exhausted");
+        };
+        assert_eq!(
+            expected.method, method,
+            "ReplayFoo: call #{call} used the wrong method"
+        );
+        (call, expected)
+    }
+}
+
+fn check(call: usize, expected: &Interaction, output: Option<&Zed>) {
+    assert_eq!(
+        expected.output.as_ref(),
+        output,
+        "ReplayFoo: call #{call} ({:?}) produced a different state",
+        expected.tag
+    );
+}
+
+impl Foo for ReplayFoo {
+    async fn get(&self) -> Result<Zed, FooError> {
+        let (_, expected) = self.next(Method::Get);
+        expected.failure()?;
+        Ok(expected.input)
+    }
+
+    async fn bar<U>(&self, update_fn: U) -> Result<Zed, FooError>
+    where
+        U: FnOnce(Zed) -> Zed + Send + 'static,
+    {
+        let (call, expected) = self.next(Method::Bar);
+        let output = expected
+            .ran_update()
+            .then(|| update_fn(expected.input.clone()));
+        check(call, &expected, output.as_ref());
+        expected.failure()?;
+        Ok(output.expect("an update that did not fail was run"))
+    }
+
+    async fn try_bar<U, E>(&self, update_fn: U) -> Result<Zed, E>
+    where
+        U: FnOnce(Zed) -> Result<Zed, E> + Send + 'static,
+        E: From<FooError> + Send + 'static,
+    {
+        let (call, expected) = self.next(Method::TryBar);
+        let output = expected
+            .ran_update()
+            .then(|| update_fn(expected.input.clone()));
+        check(
+            call,
+            &expected,
+            output.as_ref().and_then(|output| output.as_ref().ok()),
+        );
+        expected.failure()?;
+        output.expect("an update that did not fail was run")
+    }
+
+    async fn async_bar<U>(&self, update_fn: U) -> Result<Zed, FooError>
+    where
+        U: FnOnce(Zed) -> BoxFuture<'static, Zed> + Send + 'static,
+    {
+        let (call, expected) = self.next(Method::AsyncBar);
+        let output = if expected.ran_update() {
+            Some(update_fn(expected.input.clone()).await)
+        } else {
+            None
+        };
+        check(call, &expected, output.as_ref());
+        expected.failure()?;
+        Ok(output.expect("an update that did not fail was run"))
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{BazError, BazImpl, InMemoryFoo, VersionedFoo};
+
+    async fn session<F: Foo>(baz: &BazImpl<F>) {
+        baz.set_name("recorded").await.unwrap();
+        baz.increment(2).await.unwrap();
+        baz.append_entry("").await.unwrap_err();
+        baz.append_entry("a").await.unwrap();
+        baz.get().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_records_each_update() {
+        let recording = RecordingFoo::new(InMemoryFoo::default());
+        let baz = BazImpl::new(recording.with_tag("baz"));
+
+        session(&baz).await;
+
+        let trace = recording.trace();
+        assert_eq!(trace.len(), 5);
+        assert!(trace.iter().all(|interaction| interaction.tag == "baz"));
+        assert_eq!(trace[1].method, Method::TryBar);
+        assert_eq!(trace[1].input.name, "recorded");
+        assert_eq!(trace[1].output.as_ref().unwrap().counter, 2);
+        assert_eq!(trace[2].output, None);
+        assert_eq!(
+            trace[3].output.as_ref(),
+            Some(&recording.inner().snapshot().await)
+        );
+        assert_eq!(trace[4].method, Method::Get);
+        assert_eq!(trace[4].output, trace[3].output);
+    }
+
+    #[cfg(feature = "serde")]
+    #[tokio::test]
+    async fn test_replays_dumped_trace() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.json");
+
+        let recording = RecordingFoo::new(InMemoryFoo::default());
+        session(&BazImpl::new(recording.with_tag("baz"))).await;
+        recording.dump(&path).await.unwrap();
+
+        let replay = ReplayFoo::load(&path).await.unwrap();
+        let baz = BazImpl::new(replay);
+        session(&baz).await;
+        baz.store().assert_finished();
+    }
+
+    #[tokio::test]
+    async fn test_store_errors_are_recorded_and_replayed() {
+        let store = Arc::new(VersionedFoo::default());
+        let recording = RecordingFoo::new(Arc::clone(&store));
+        let conflicting = |store: Arc<VersionedFoo>| {
+            move |mut zed: Zed| {
+                // Commit behind the update's back so its own commit conflicts.
+                store.compare_and_swap(zed.version, Zed::default()).unwrap();
+                zed.counter += 1;
+                zed
+            }
+        };
+
+        let update = conflicting(Arc::clone(&store));
+        let bar = recording.bar(update).await;
+        let update = conflicting(Arc::clone(&store));
+        let try_bar = recording
+            .try_bar(move |zed| Ok::<_, BazError>(update(zed)))
+            .await;
+        assert!(matches!(bar, Err(FooError::Conflict { .. })));
+        assert!(matches!(
+            try_bar,
+            Err(BazError::Store(FooError::Conflict { .. }))
+        ));
+
+        let trace = recording.trace();
+        assert_eq!(trace.len(), 2);
+        for interaction in &trace {
+            assert_eq!(interaction.output.as_ref().unwrap().counter, 1);
+            assert!(interaction.error.as_ref().unwrap().contains("conflict"));
+        }
+
+        let replay = ReplayFoo::new(trace);
+        let increment = |mut zed: Zed| {
+            zed.counter += 1;
+            zed
+        };
+        let bar = replay.bar(increment).await;
+        let try_bar = replay
+            .try_bar(move |zed| Ok::<_, BazError>(increment(zed)))
+            .await;
+        assert!(matches!(bar, Err(FooError::Unavailable(_))));
+        assert!(matches!(
+            try_bar,
+            Err(BazError::Store(FooError::Unavailable(_)))
+        ));
+        replay.assert_finished();
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "produced a different state")]
+    async fn test_replay_catches_deviation() {
+        let recording = RecordingFoo::new(InMemoryFoo::default());
+        BazImpl::new(recording.with_tag("baz"))
+            .increment(2)
+            .await
+            .unwrap();
+
+        let baz = BazImpl::new(ReplayFoo::new(recording.trace()));
+        let _ = baz.increment(3).await;
+    }
+}
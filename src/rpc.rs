@@ -0,0 +1,309 @@
+use std::future::Future;
+use std::io::{self, ErrorKind};
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+
+use crate::{BazError, BazImpl, Foo, FooError, Operation, Zed};
+
+/// Largest frame either side accepts, in bytes.
+pub const MAX_FRAME: u32 = 1 << 20;
+
+/// A call to the daemon. Frames hold JSON such as `{"type":"get"}` or
+/// `{"type":"apply","operation":{"op":"increment","by":2}}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    Get,
+    Apply { operation: Operation },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Response {
+    State { zed: Zed },
+    Rejected { message: String },
+    Error { message: String },
+}
+
+impl From<Result<Zed, BazError>> for Response {
+    fn from(result: Result<Zed, BazError>) -> Self {
+        match result {
+            Ok(zed) => Response::State { zed },
+            Err(BazError::Rejected(message)) => Response::Rejected { message },
+            Err(BazError::Store(err)) => Response::Error {
+                message: err.to_string(),
+            },
+        }
+    }
+}
+
+impl Response {
+    /// Turns the reply back into what the call returned in the daemon. Store
+    /// errors come back as `FooError::Unavailable`.
+    pub fn into_result(self) -> Result<Zed, BazError> {
+        match self {
+            Response::State { zed } => Ok(zed),
+            Response::Rejected { message } => Err(BazError::Rejected(message)),
+            Response::Error { message } => Err(FooError::Unavailable(message).into()),
+        }
+    }
+}
+
+/// Writes one frame: the payload length as a big-endian `u32`, then the
+/// payload.
+pub async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, payload: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(payload.len())
+        .ok()
+        .filter(|len| *len <= MAX_FRAME)
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "frame too large"))?;
+    writer.write_all(&len.to_be_bytes()).await?;
+    writer.write_all(payload).await?;
+    writer.flush().await
+}
+
+/// Reads one frame, or `None` if the peer closed the stream between frames.
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len).await {
+        Ok(_) => {}
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    let len = u32::from_be_bytes(len);
+    if len > MAX_FRAME {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("frame of {len} bytes exceeds {MAX_FRAME}"),
+        ));
+    }
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload).await?;
+    Ok(Some(payload))
+}
+
+/// Binds the daemon socket at `path`, replacing a stale socket file left by
+/// a daemon that is no longer running. Anything at `path` that is not a
+/// socket is left alone and reported as `AlreadyExists`.
+pub async fn bind(path: impl AsRef<Path>) -> io::Result<UnixListener> {
+    let path = path.as_ref();
+    if UnixStream::connect(path).await.is_ok() {
+        return Err(io::Error::new(
+            ErrorKind::AddrInUse,
+            format!("a daemon is already listening on {}", path.display()),
+        ));
+    }
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) => {
+            return Err(io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            ))
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+    UnixListener::bind(path)
+}
+
+/// Serves requests on `listener` until `shutdown` resolves.
+///
+/// Each connection is handled on its own task and may send any number of
+/// requests, answered in order. A frame that is not a valid `Request` gets
+/// a `Response::Error` and the connection stays open. A failed accept is
+/// logged as a `tracing` warning when that feature is on, and the daemon
+/// keeps listening either way. On shutdown, requests already read are
+/// answered before this returns, and idle connections are closed.
+pub async fn serve<F>(
+    listener: UnixListener,
+    baz: Arc<BazImpl<F>>,
+    shutdown: impl Future<Output = ()>,
+) -> io::Result<()>
+where
+    F: Foo + Send + Sync + 'static,
+{
+    tokio::pin!(shutdown);
+    // Connections watch for this sender to be dropped.
+    let (closing, closed) = watch::channel(());
+    let mut connections = JoinSet::new();
+    loop {
+        tokio::select! {
+            () = &mut shutdown => break,
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    let baz = Arc::clone(&baz);
+                    let mut closed = closed.clone();
+                    connections.spawn(async move {
+                        let closed = async move {
+                            let _ = closed.changed().await;
+                        };
+                        let _ = handle(stream, &baz, closed).await;
+                    });
+                }
+                Err(err) => {
+                    accept_failed(&err);
+                    // Errors such as running out of file descriptors tend
+                    // to repeat; give them a moment to clear.
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+            },
+            Some(_) = connections.join_next(), if !connections.is_empty() => {}
+        }
+    }
+    drop(closing);
+    while connections.join_next().await.is_some() {}
+    Ok(())
+}
+
+#[cfg(feature = "tracing")]
+fn accept_failed(err: &io::Error) {
+    tracing::warn!(error = %err, "rpc: accept failed");
+}
+
+#[cfg(not(feature = "tracing"))]
+fn accept_failed(_err: &io::Error) {}
+
+/// Answers requests on `stream` until the peer hangs up or `closed`
+/// resolves between requests.
+async fn handle<F: Foo>(
+    mut stream: UnixStream,
+    baz: &BazImpl<F>,
+    closed: impl Future<Output = ()>,
+) -> io::Result<()> {
+    tokio::pin!(closed);
+    loop {
+        let frame = tokio::select! {
+            () = &mut closed => return Ok(()),
+            frame = read_frame(&mut stream) => match frame? {
+                Some(frame) => frame,
+                None => return Ok(()),
+            },
+        };
+        let response = match serde_json::from_slice(&frame) {
+            Ok(Request::Get) => baz.get().await.map_err(BazError::from).into(),
+            Ok(Request::Apply { operation }) => baz.apply(operation).await.into(),
+            Err(err) => Response::Error {
+                message: format!("invalid request: {err}"),
+            },
+        };
+        write_frame(&mut stream, &serde_json::to_vec(&response)?).await?;
+    }
+}
+
+/// A connection to a daemon started with `serve`.
+pub struct RpcClient {
+    stream: UnixStream,
+}
+
+impl RpcClient {
+    pub async fn connect(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            stream: UnixStream::connect(path).await?,
+        })
+    }
+
+    pub async fn call(&mut self, request: &Request) -> io::Result<Response> {
+        write_frame(&mut self.stream, &serde_json::to_vec(request)?).await?;
+        let frame = read_frame(&mut self.stream)
+            .await?
+            .ok_or_else(|| io::Error::new(ErrorKind::UnexpectedEof, "daemon hung up"))?;
+        Ok(serde_json::from_slice(&frame)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::InMemoryFoo;
+    use tokio::sync::oneshot;
+
+    #[tokio::test]
+    async fn test_clients_share_one_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("zed.sock");
+        let listener = bind(&path).await.unwrap();
+        let (stop, stopped) = oneshot::channel::<()>();
+        let daemon = tokio::spawn(serve(
+            listener,
+            Arc::new(BazImpl::new(InMemoryFoo::default())),
+            async {
+                let _ = stopped.await;
+            },
+        ));
+
+        let mut first = RpcClient::connect(&path).await.unwrap();
+        let mut second = RpcClient::connect(&path).await.unwrap();
+        let increment = Request::Apply {
+            operation: Operation::Increment { by: 2 },
+        };
+        first.call(&increment).await.unwrap();
+        second.call(&increment).await.unwrap();
+
+        let rejected = Request::Apply {
+            operation: Operation::AppendEntry {
+                entry: String::new(),
+            },
+        };
+        assert!(matches!(
+            first.call(&rejected).await.unwrap(),
+            Response::Rejected { .. }
+        ));
+        let state = second.call(&Request::Get).await.unwrap().into_result();
+        assert_eq!(state.unwrap().counter, 4);
+
+        assert!(bind(&path).await.is_err());
+        stop.send(()).unwrap();
+        daemon.await.unwrap().unwrap();
+        // Idle connections are closed rather than keeping shutdown waiting.
+        assert!(first.call(&Request::Get).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_bind_leaves_other_files_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("zed.sock");
+        std::fs::write(&path, "not a socket").unwrap();
+
+        let err = bind(&path).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "not a socket");
+
+        std::fs::remove_file(&path).unwrap();
+        drop(bind(&path).await.unwrap());
+        // What a daemon that died leaves behind is replaced.
+        bind(&path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_bad_frames() {
+        let (mut client, server) = UnixStream::pair().unwrap();
+        let baz = BazImpl::new(InMemoryFoo::default());
+        let server =
+            tokio::spawn(async move { handle(server, &baz, std::future::pending()).await });
+
+        write_frame(&mut client, b"{\"type\":\"launch\"}")
+            .await
+            .unwrap();
+        let frame = read_frame(&mut client).await.unwrap().unwrap();
+        assert!(matches!(
+            serde_json::from_slice(&frame).unwrap(),
+            Response::Error { .. }
+        ));
+
+        client
+            .write_all(&(MAX_FRAME + 1).to_be_bytes())
+            .await
+            .unwrap();
+        let err = server.await.unwrap().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
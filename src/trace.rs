@@ -0,0 +1,299 @@
+use std::future::Future;
+
+use crate::{BazError, Zed};
+
+/// Runs one `BazImpl` operation, inside a `baz.operation` span when the
+/// `tracing` feature is on.
+#[cfg(feature = "tracing")]
+pub(crate) async fn operation<O>(name: &'static str, op: O) -> Result<Zed, BazError>
+where
+    O: Future<Output = Result<Zed, BazError>>,
+{
+    use tracing::Instrument;
+
+    let span = tracing::info_span!(
+        "baz.operation",
+        operation = name,
+        outcome = tracing::field::Empty
+    );
+    let result = op.instrument(span.clone()).await;
+    span.record(
+        "outcome",
+        match &result {
+            Ok(_) => "ok",
+            Err(BazError::Rejected(_)) => "rejected",
+            Err(BazError::Store(_)) => "error",
+        },
+    );
+    result
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) async fn operation<O>(_name: &'static str, op: O) -> Result<Zed, BazError>
+where
+    O: Future<Output = Result<Zed, BazError>>,
+{
+    op.await
+}
+
+#[cfg(feature = "tracing")]
+pub use traced::{CapturedSpan, SpanCapture, TracedFoo, TracingLayer};
+
+#[cfg(feature = "tracing")]
+mod traced {
+    use std::collections::BTreeMap;
+    use std::fmt;
+    use std::future::Future;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+
+    use tracing::field::{Empty, Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Instrument, Span, Subscriber};
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    use tracing_subscriber::registry::LookupSpan;
+    use tracing_subscriber::Layer;
+
+    use crate::{BoxFuture, Foo, FooError, FooLayer, Zed};
+
+    /// Wraps a store in `TracedFoo`.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct TracingLayer;
+
+    impl<S: Foo + Sync> FooLayer<S> for TracingLayer {
+        type Foo = TracedFoo<S>;
+
+        fn layer(&self, inner: S) -> TracedFoo<S> {
+            TracedFoo { inner }
+        }
+    }
+
+    /// A `Foo` decorator that runs every call inside a `foo.bar` span.
+    ///
+    /// The span records the `method` called, the `version_before` the
+    /// update saw, the committed `version_after`, `duration_us` and an
+    /// `outcome` of `ok`, `rejected` or `error`.
+    pub struct TracedFoo<S> {
+        inner: S,
+    }
+
+    fn span(method: &'static str) -> Span {
+        tracing::info_span!(
+            "foo.bar",
+            method,
+            version_before = Empty,
+            version_after = Empty,
+            duration_us = Empty,
+            outcome = Empty
+        )
+    }
+
+    async fn traced<E, C>(span: Span, rejected: Arc<AtomicBool>, call: C) -> Result<Zed, E>
+    where
+        C: Future<Output = Result<Zed, E>>,
+    {
+        let started = Instant::now();
+        let result = call.instrument(span.clone()).await;
+        span.record("duration_us", started.elapsed().as_micros() as u64);
+        match &result {
+            Ok(zed) => {
+                span.record("version_after", zed.version);
+                span.record("outcome", "ok");
+            }
+            Err(_) if rejected.load(Ordering::Relaxed) => {
+                span.record("outcome", "rejected");
+            }
+            Err(_) => {
+                span.record("outcome", "error");
+            }
+        }
+        result
+    }
+
+    impl<S: Foo + Sync> Foo for TracedFoo<S> {
+        fn get(&self) -> impl Future<Output = Result<Zed, FooError>> + Send {
+            traced(span("get"), Arc::default(), self.inner.get())
+        }
+
+        fn bar<F>(&self, update_fn: F) -> impl Future<Output = Result<Zed, FooError>> + Send
+        where
+            F: FnOnce(Zed) -> Zed + Send + 'static,
+        {
+            let span = span("bar");
+            let recorder = span.clone();
+            let call = self.inner.bar(move |zed| {
+                recorder.record("version_before", zed.version);
+                update_fn(zed)
+            });
+            traced(span, Arc::default(), call)
+        }
+
+        fn try_bar<F, E>(&self, update_fn: F) -> impl Future<Output = Result<Zed, E>> + Send
+        where
+            F: FnOnce(Zed) -> Result<Zed, E> + Send + 'static,
+            E: From<FooError> + Send + 'static,
+        {
+            let span = span("try_bar");
+            let recorder = span.clone();
+            let rejected = Arc::new(AtomicBool::new(false));
+            let flag = Arc::clone(&rejected);
+            let call = self.inner.try_bar(move |zed| {
+                recorder.record("version_before", zed.version);
+                let result = update_fn(zed);
+                flag.store(result.is_err(), Ordering::Relaxed);
+                result
+            });
+            traced(span, rejected, call)
+        }
+
+        fn async_bar<F>(&self, update_fn: F) -> impl Future<Output = Result<Zed, FooError>> + Send
+        where
+            F: FnOnce(Zed) -> BoxFuture<'static, Zed> + Send + 'static,
+        {
+            let span = span("async_bar");
+            let recorder = span.clone();
+            let call = self.inner.async_bar(move |zed| {
+                recorder.record("version_before", zed.version);
+                update_fn(zed)
+            });
+            traced(span, Arc::default(), call)
+        }
+    }
+
+    /// A span seen by `SpanCapture`, with its fields rendered as strings.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct CapturedSpan {
+        pub name: &'static str,
+        pub parent: Option<&'static str>,
+        pub fields: BTreeMap<String, String>,
+    }
+
+    impl CapturedSpan {
+        pub fn field(&self, name: &str) -> Option<&str> {
+            self.fields.get(name).map(String::as_str)
+        }
+    }
+
+    /// Collects spans for tests to assert on.
+    ///
+    /// `set_default` installs the collector for the current thread only, so
+    /// use it with a current-thread runtime such as plain `#[tokio::test]`.
+    #[derive(Clone, Default)]
+    pub struct SpanCapture {
+        spans: Arc<Mutex<Vec<CapturedSpan>>>,
+    }
+
+    impl SpanCapture {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        #[must_use = "spans are only captured while the guard is alive"]
+        pub fn set_default(&self) -> tracing::subscriber::DefaultGuard {
+            let subscriber = tracing_subscriber::registry().with(self.clone());
+            tracing::subscriber::set_default(subscriber)
+        }
+
+        /// Spans in the order they were created.
+        pub fn spans(&self) -> Vec<CapturedSpan> {
+            self.spans.lock().unwrap().clone()
+        }
+
+        pub fn named(&self, name: &str) -> Vec<CapturedSpan> {
+            self.spans()
+                .into_iter()
+                .filter(|span| span.name == name)
+                .collect()
+        }
+    }
+
+    struct Slot(usize);
+
+    struct FieldVisitor<'a>(&'a mut BTreeMap<String, String>);
+
+    impl Visit for FieldVisitor<'_> {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name().to_string(), value.to_string());
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0
+                .insert(field.name().to_string(), format!("{value:?}"));
+        }
+    }
+
+    impl<S> Layer<S> for SpanCapture
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+            let span = ctx.span(id).expect("span is registered");
+            let mut fields = BTreeMap::new();
+            attrs.record(&mut FieldVisitor(&mut fields));
+
+            let mut spans = self.spans.lock().unwrap();
+            spans.push(CapturedSpan {
+                name: span.name(),
+                parent: span.parent().map(|parent| parent.name()),
+                fields,
+            });
+            span.extensions_mut().insert(Slot(spans.len() - 1));
+        }
+
+        fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+            let span = ctx.span(id).expect("span is registered");
+            let extensions = span.extensions();
+            let Some(Slot(index)) = extensions.get::<Slot>() else {
+                return;
+            };
+            let mut spans = self.spans.lock().unwrap();
+            values.record(&mut FieldVisitor(&mut spans[*index].fields));
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+
+    use super::*;
+    use crate::{BazImpl, FooBuilder, VersionedFoo};
+
+    #[tokio::test]
+    async fn test_baz_operations_produce_spans() {
+        let capture = SpanCapture::new();
+        let _guard = capture.set_default();
+
+        let foo = FooBuilder::new(VersionedFoo::default())
+            .layer(TracingLayer)
+            .build();
+        let baz = BazImpl::new(foo);
+        baz.increment(2).await.unwrap();
+        baz.append_entry("").await.unwrap_err();
+
+        let operations = capture.named("baz.operation");
+        let names: Vec<_> = operations
+            .iter()
+            .map(|span| {
+                (
+                    span.field("operation").unwrap(),
+                    span.field("outcome").unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(names, [("increment", "ok"), ("append_entry", "rejected")]);
+
+        let calls = capture.named("foo.bar");
+        assert_eq!(calls.len(), 2);
+        assert!(calls
+            .iter()
+            .all(|span| span.parent == Some("baz.operation")));
+        assert_eq!(calls[0].field("method"), Some("try_bar"));
+        assert_eq!(calls[0].field("version_before"), Some("0"));
+        assert_eq!(calls[0].field("version_after"), Some("1"));
+        assert_eq!(calls[0].field("outcome"), Some("ok"));
+        assert!(calls[0].field("duration_us").is_some());
+        assert_eq!(calls[1].field("outcome"), Some("rejected"));
+        assert_eq!(calls[1].field("version_after"), None);
+    }
+}
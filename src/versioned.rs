@@ -0,0 +1,228 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::{BoxFuture, Foo, FooError, RetryFoo, Zed};
+
+/// How `VersionedFoo::bar_retrying` backs off between conflicting attempts.
+///
+/// The wait starts at `initial_backoff` and doubles after each conflict,
+/// never exceeding `max_backoff`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+/// A `Foo` whose writes are compare-and-swap on `Zed::version`.
+///
+/// Update closures run without holding the lock; the result is only stored
+/// if nobody else committed in the meantime, and every commit bumps the
+/// version by one. `bar` gets a single attempt because its `FnOnce` cannot
+/// be re-run, while `bar_retrying` retries according to the `RetryPolicy`.
+#[derive(Default)]
+pub struct VersionedFoo {
+    state: Mutex<Zed>,
+    policy: RetryPolicy,
+}
+
+impl VersionedFoo {
+    pub fn new(zed: Zed) -> Self {
+        Self {
+            state: Mutex::new(zed),
+            policy: RetryPolicy::default(),
+        }
+    }
+
+    /// A `max_attempts` of 0 is treated as 1, so the update always runs.
+    pub fn with_policy(mut self, policy: RetryPolicy) -> Self {
+        self.policy = RetryPolicy {
+            max_attempts: policy.max_attempts.max(1),
+            ..policy
+        };
+        self
+    }
+
+    pub fn snapshot(&self) -> Zed {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Stores `zed` as version `expected_version + 1` if the current version
+    /// is still `expected_version`, otherwise returns the current state.
+    pub fn compare_and_swap(&self, expected_version: u64, mut zed: Zed) -> Result<Zed, Zed> {
+        let mut state = self.state.lock().unwrap();
+        if state.version != expected_version {
+            return Err(state.clone());
+        }
+        zed.version = expected_version + 1;
+        *state = zed.clone();
+        Ok(zed)
+    }
+}
+
+impl Foo for VersionedFoo {
+    async fn get(&self) -> Result<Zed, FooError> {
+        Ok(self.snapshot())
+    }
+
+    async fn bar<F>(&self, update_fn: F) -> Result<Zed, FooError>
+    where
+        F: FnOnce(Zed) -> Zed + Send + 'static,
+    {
+        self.try_bar(move |zed| Ok::<_, FooError>(update_fn(zed)))
+            .await
+    }
+
+    async fn try_bar<F, E>(&self, update_fn: F) -> Result<Zed, E>
+    where
+        F: FnOnce(Zed) -> Result<Zed, E> + Send + 'static,
+        E: From<FooError> + Send + 'static,
+    {
+        let current = self.snapshot();
+        let version = current.version;
+        let zed = update_fn(current)?;
+        self.compare_and_swap(version, zed)
+            .map_err(|_| FooError::Conflict { attempts: 1 }.into())
+    }
+
+    async fn async_bar<F>(&self, update_fn: F) -> Result<Zed, FooError>
+    where
+        F: FnOnce(Zed) -> BoxFuture<'static, Zed> + Send + 'static,
+    {
+        let current = self.snapshot();
+        let version = current.version;
+        let zed = update_fn(current).await;
+        self.compare_and_swap(version, zed)
+            .map_err(|_| FooError::Conflict { attempts: 1 })
+    }
+}
+
+impl RetryFoo for VersionedFoo {
+    async fn bar_retrying<F>(&self, update_fn: F) -> Result<Zed, FooError>
+    where
+        F: Fn(Zed) -> Zed + Send + Sync + 'static,
+    {
+        let mut backoff = self.policy.initial_backoff.min(self.policy.max_backoff);
+        let mut current = self.snapshot();
+        for attempt in 1..=self.policy.max_attempts {
+            let version = current.version;
+            match self.compare_and_swap(version, update_fn(current)) {
+                Ok(zed) => return Ok(zed),
+                Err(latest) => current = latest,
+            }
+            if attempt < self.policy.max_attempts {
+                tokio::time::sleep(backoff).await;
+                backoff = backoff.saturating_mul(2).min(self.policy.max_backoff);
+            }
+        }
+        Err(FooError::Conflict {
+            attempts: self.policy.max_attempts,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_retries_all_commit() {
+        let policy = RetryPolicy {
+            max_attempts: 1_000,
+            ..RetryPolicy::default()
+        };
+        let foo = Arc::new(VersionedFoo::default().with_policy(policy));
+
+        let tasks: Vec<_> = (0..32)
+            .map(|_| {
+                let foo = Arc::clone(&foo);
+                tokio::spawn(async move {
+                    foo.bar_retrying(|mut zed| {
+                        zed.counter += 1;
+                        zed
+                    })
+                    .await
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+
+        let zed = foo.snapshot();
+        assert_eq!(zed.counter, 32);
+        assert_eq!(zed.version, 32);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_attempts() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            ..RetryPolicy::default()
+        };
+        let foo = Arc::new(VersionedFoo::default().with_policy(policy));
+
+        let rival = Arc::clone(&foo);
+        let result = foo
+            .bar_retrying(move |zed| {
+                let current = rival.snapshot();
+                rival.compare_and_swap(current.version, current).unwrap();
+                zed
+            })
+            .await;
+
+        assert!(matches!(result, Err(FooError::Conflict { attempts: 3 })));
+        assert_eq!(foo.snapshot().version, 3);
+    }
+
+    #[tokio::test]
+    async fn test_huge_backoff_is_capped() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::MAX,
+            max_backoff: Duration::from_millis(1),
+        };
+        let foo = Arc::new(VersionedFoo::default().with_policy(policy));
+
+        let rival = Arc::clone(&foo);
+        let result = foo
+            .bar_retrying(move |zed| {
+                let current = rival.snapshot();
+                rival.compare_and_swap(current.version, current).unwrap();
+                zed
+            })
+            .await;
+
+        assert!(matches!(result, Err(FooError::Conflict { attempts: 3 })));
+    }
+
+    #[tokio::test]
+    async fn test_zero_max_attempts_still_runs_the_update() {
+        let policy = RetryPolicy {
+            max_attempts: 0,
+            ..RetryPolicy::default()
+        };
+        let foo = VersionedFoo::default().with_policy(policy);
+
+        let zed = foo
+            .bar_retrying(|mut zed| {
+                zed.counter += 1;
+                zed
+            })
+            .await
+            .unwrap();
+        assert_eq!(zed.counter, 1);
+    }
+}
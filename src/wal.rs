@@ -0,0 +1,388 @@
+use std::fmt;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::codec::{Codec, TextCodec};
+use crate::file::write_atomic;
+use crate::{BoxFuture, Foo, FooError, Zed};
+
+const SNAPSHOT: &str = "snapshot";
+const LOG: &str = "wal.log";
+
+/// When `WalFoo` fsyncs the log after appending a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Durability {
+    /// After every record; a committed update survives a power loss.
+    Always,
+    /// After every `n`th record; up to `n - 1` updates may be lost.
+    Every(u32),
+    /// Never; the OS decides when records reach the disk.
+    Never,
+}
+
+/// Written as `always`, `never` or `every:<n>`.
+impl fmt::Display for Durability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Durability::Always => f.write_str("always"),
+            Durability::Every(n) => write!(f, "every:{n}"),
+            Durability::Never => f.write_str("never"),
+        }
+    }
+}
+
+impl FromStr for Durability {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "always" => Ok(Durability::Always),
+            "never" => Ok(Durability::Never),
+            _ => s
+                .strip_prefix("every:")
+                .and_then(|n| n.parse().ok())
+                .map(Durability::Every)
+                .ok_or_else(|| {
+                    format!("unknown durability {s:?}, expected always, never or every:<n>")
+                }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalConfig {
+    pub durability: Durability,
+    /// Records appended before the log is folded into the snapshot.
+    pub compact_after: usize,
+}
+
+impl Default for WalConfig {
+    fn default() -> Self {
+        Self {
+            durability: Durability::Always,
+            compact_after: 1_000,
+        }
+    }
+}
+
+struct WalState {
+    zed: Zed,
+    log: File,
+    log_len: u64,
+    records: usize,
+    unsynced: u32,
+}
+
+/// A `Foo` backed by a write-ahead log in a directory.
+///
+/// Each committed update appends one checksummed record holding the new
+/// state, so a crash mid-write loses at most the torn record, which is
+/// dropped on the next `open`. Once `compact_after` records accumulate the
+/// state is written to an atomically replaced snapshot and the log is
+/// truncated. Records hold whole states, so replaying a log that was
+/// already folded into the snapshot is harmless.
+pub struct WalFoo {
+    dir: PathBuf,
+    config: WalConfig,
+    codec: Box<dyn Codec>,
+    state: Mutex<WalState>,
+}
+
+impl WalFoo {
+    /// Opens the store in `dir`, creating it if needed, and rebuilds the
+    /// latest state from the snapshot and the log. Records and snapshot use
+    /// the text format.
+    pub async fn open(dir: impl Into<PathBuf>, config: WalConfig) -> Result<Self, FooError> {
+        Self::open_with_codec(dir, config, TextCodec).await
+    }
+
+    /// Like `open`, but records and snapshot are written with `codec`.
+    pub async fn open_with_codec(
+        dir: impl Into<PathBuf>,
+        config: WalConfig,
+        codec: impl Codec + 'static,
+    ) -> Result<Self, FooError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).await?;
+
+        let mut zed = match fs::read(dir.join(SNAPSHOT)).await {
+            Ok(bytes) => codec.decode(&bytes)?,
+            Err(err) if err.kind() == ErrorKind::NotFound => Zed::default(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let bytes = match fs::read(dir.join(LOG)).await {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into()),
+        };
+        let (records, valid_len) = replay(&codec, &bytes)?;
+        if let Some(last) = records.last() {
+            zed = last.clone();
+        }
+
+        let log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(LOG))
+            .await?;
+        if valid_len < bytes.len() {
+            log.set_len(valid_len as u64).await?;
+            log.sync_data().await?;
+        }
+
+        Ok(Self {
+            dir,
+            config,
+            codec: Box::new(codec),
+            state: Mutex::new(WalState {
+                zed,
+                log,
+                log_len: valid_len as u64,
+                records: records.len(),
+                unsynced: 0,
+            }),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub async fn snapshot(&self) -> Zed {
+        self.state.lock().await.zed.clone()
+    }
+
+    /// Folds the log into the snapshot now rather than waiting for
+    /// `compact_after`.
+    pub async fn compact(&self) -> Result<(), FooError> {
+        let mut state = self.state.lock().await;
+        self.compact_locked(&mut state).await
+    }
+
+    async fn compact_locked(&self, state: &mut WalState) -> Result<(), FooError> {
+        write_atomic(&self.dir.join(SNAPSHOT), &self.codec.encode(&state.zed)).await?;
+        state.log.set_len(0).await?;
+        state.log.sync_data().await?;
+        state.log_len = 0;
+        state.records = 0;
+        state.unsynced = 0;
+        Ok(())
+    }
+
+    async fn commit(&self, state: &mut WalState, zed: Zed) -> Result<Zed, FooError> {
+        let record = frame(&self.codec.encode(&zed));
+        if let Err(err) = append(state, &record, self.config.durability).await {
+            // Cut off whatever part of the record made it out so the next
+            // append does not land behind garbage.
+            let _ = state.log.set_len(state.log_len).await;
+            return Err(err);
+        }
+        state.log_len += record.len() as u64;
+        state.records += 1;
+        state.zed = zed.clone();
+
+        // The update is already in the log, so a failed compaction must not
+        // fail it; the next commit tries again.
+        if state.records >= self.config.compact_after {
+            let _ = self.compact_locked(state).await;
+        }
+        Ok(zed)
+    }
+}
+
+impl Foo for WalFoo {
+    async fn get(&self) -> Result<Zed, FooError> {
+        Ok(self.snapshot().await)
+    }
+
+    async fn bar<F>(&self, update_fn: F) -> Result<Zed, FooError>
+    where
+        F: FnOnce(Zed) -> Zed + Send + 'static,
+    {
+        self.try_bar(move |zed| Ok::<_, FooError>(update_fn(zed)))
+            .await
+    }
+
+    async fn try_bar<F, E>(&self, update_fn: F) -> Result<Zed, E>
+    where
+        F: FnOnce(Zed) -> Result<Zed, E> + Send + 'static,
+        E: From<FooError> + Send + 'static,
+    {
+        let mut state = self.state.lock().await;
+        let zed = update_fn(state.zed.clone())?;
+        Ok(self.commit(&mut state, zed).await?)
+    }
+
+    async fn async_bar<F>(&self, update_fn: F) -> Result<Zed, FooError>
+    where
+        F: FnOnce(Zed) -> BoxFuture<'static, Zed> + Send + 'static,
+    {
+        let mut state = self.state.lock().await;
+        let zed = update_fn(state.zed.clone()).await;
+        self.commit(&mut state, zed).await
+    }
+}
+
+async fn append(
+    state: &mut WalState,
+    record: &[u8],
+    durability: Durability,
+) -> Result<(), FooError> {
+    state.log.write_all(record).await?;
+    state.log.flush().await?;
+
+    state.unsynced += 1;
+    let sync = match durability {
+        Durability::Always => true,
+        Durability::Every(n) => state.unsynced >= n,
+        Durability::Never => false,
+    };
+    if sync {
+        state.log.sync_data().await?;
+        state.unsynced = 0;
+    }
+    Ok(())
+}
+
+/// A record is a `<len> <checksum>\n` header followed by `len` payload bytes.
+fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut record = format!("{} {:016x}\n", payload.len(), checksum(payload)).into_bytes();
+    record.extend_from_slice(payload);
+    record
+}
+
+/// Decodes every intact record in `bytes`, stopping at the first torn or
+/// mismatched one, and returns them along with the length they cover.
+fn replay(codec: &dyn Codec, bytes: &[u8]) -> Result<(Vec<Zed>, usize), FooError> {
+    let mut records = Vec::new();
+    let mut offset = 0;
+    while let Some((payload, next)) = unframe(bytes, offset) {
+        records.push(codec.decode(payload)?);
+        offset = next;
+    }
+    Ok((records, offset))
+}
+
+fn unframe(bytes: &[u8], offset: usize) -> Option<(&[u8], usize)> {
+    let rest = &bytes[offset..];
+    let newline = rest.iter().position(|&b| b == b'\n')?;
+    let header = std::str::from_utf8(&rest[..newline]).ok()?;
+    let (len, sum) = header.split_once(' ')?;
+    let len: usize = len.parse().ok()?;
+    let sum = u64::from_str_radix(sum, 16).ok()?;
+
+    let start = newline + 1;
+    let payload = rest.get(start..start.checked_add(len)?)?;
+    (checksum(payload) == sum).then_some((payload, offset + start + len))
+}
+
+/// FNV-1a, enough to spot a torn or bit-flipped record.
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn increment(mut zed: Zed) -> Zed {
+        zed.counter += 1;
+        zed
+    }
+
+    #[tokio::test]
+    async fn test_reopen_replays_log_and_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = WalConfig {
+            compact_after: 2,
+            ..WalConfig::default()
+        };
+
+        let foo = WalFoo::open(dir.path(), config.clone()).await.unwrap();
+        for _ in 0..3 {
+            foo.bar(increment).await.unwrap();
+        }
+        let zed = foo
+            .bar(|zed| Zed {
+                name: "wal".to_string(),
+                ..zed
+            })
+            .await
+            .unwrap();
+        drop(foo);
+
+        assert!(dir.path().join(SNAPSHOT).exists());
+        let log = std::fs::read(dir.path().join(LOG)).unwrap();
+        let (records, _) = replay(&TextCodec, &log).unwrap();
+        assert_eq!(records.len(), 0);
+
+        let reopened = WalFoo::open(dir.path(), config).await.unwrap();
+        assert_eq!(reopened.snapshot().await, zed);
+        assert_eq!(zed.counter, 3);
+    }
+
+    #[tokio::test]
+    async fn test_failed_compaction_keeps_the_commit() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = WalConfig {
+            compact_after: 1,
+            ..WalConfig::default()
+        };
+        let foo = WalFoo::open(dir.path(), config.clone()).await.unwrap();
+        // A non-empty directory cannot be renamed over, so compaction fails.
+        std::fs::create_dir_all(dir.path().join(SNAPSHOT).join("blocker")).unwrap();
+
+        let zed = foo.bar(increment).await.unwrap();
+        assert_eq!(foo.snapshot().await, zed);
+        let log = std::fs::read(dir.path().join(LOG)).unwrap();
+        assert_eq!(replay(&TextCodec, &log).unwrap().0.len(), 1);
+
+        std::fs::remove_dir_all(dir.path().join(SNAPSHOT)).unwrap();
+        let zed = foo.bar(increment).await.unwrap();
+        drop(foo);
+        let reopened = WalFoo::open(dir.path(), config).await.unwrap();
+        assert_eq!(reopened.snapshot().await, zed);
+        assert_eq!(zed.counter, 2);
+    }
+
+    #[tokio::test]
+    async fn test_torn_record_is_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let foo = WalFoo::open(dir.path(), WalConfig::default())
+            .await
+            .unwrap();
+        foo.bar(increment).await.unwrap();
+        let zed = foo.bar(increment).await.unwrap();
+        drop(foo);
+
+        let mut log = std::fs::OpenOptions::new()
+            .append(true)
+            .open(dir.path().join(LOG))
+            .unwrap();
+        std::io::Write::write_all(&mut log, b"42 00000000000000ff\nname tor").unwrap();
+        drop(log);
+
+        let reopened = WalFoo::open(dir.path(), WalConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(reopened.snapshot().await, zed);
+
+        let zed = reopened.bar(increment).await.unwrap();
+        drop(reopened);
+        let reopened = WalFoo::open(dir.path(), WalConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(reopened.snapshot().await, zed);
+        assert_eq!(zed.counter, 3);
+    }
+}
@@ -0,0 +1,84 @@
+/// The state record that `Foo::bar` hands to its update closure.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Zed {
+    pub name: String,
+    pub counter: u64,
+    pub entries: Vec<String>,
+    pub version: u64,
+}
+
+impl Zed {
+    pub fn builder() -> ZedBuilder {
+        ZedBuilder::default()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ZedBuilder {
+    zed: Zed,
+}
+
+impl ZedBuilder {
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.zed.name = name.into();
+        self
+    }
+
+    pub fn counter(mut self, counter: u64) -> Self {
+        self.zed.counter = counter;
+        self
+    }
+
+    pub fn entry(mut self, entry: impl Into<String>) -> Self {
+        self.zed.entries.push(entry.into());
+        self
+    }
+
+    pub fn entries<I, S>(mut self, entries: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.zed.entries.extend(entries.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn version(mut self, version: u64) -> Self {
+        self.zed.version = version;
+        self
+    }
+
+    pub fn build(self) -> Zed {
+        self.zed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_builder() {
+        let zed = Zed::builder()
+            .name("zed")
+            .counter(3)
+            .entry("a")
+            .entries(["b", "c"])
+            .version(2)
+            .build();
+
+        assert_eq!(
+            zed,
+            Zed {
+                name: "zed".to_string(),
+                counter: 3,
+                entries: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+                version: 2,
+            }
+        );
+        assert_eq!(Zed::builder().build(), Zed::default());
+    }
+}
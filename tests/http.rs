@@ -0,0 +1,130 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use rust_mock_challenge::http;
+use rust_mock_challenge::{BazImpl, ChaosFoo, ChaosPolicy, Foo, InMemoryFoo, VersionedFoo};
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+
+struct Server {
+    addr: SocketAddr,
+    stop: oneshot::Sender<()>,
+    task: tokio::task::JoinHandle<std::io::Result<()>>,
+}
+
+impl Server {
+    async fn start<F: Foo + Send + Sync + 'static>(store: F) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (stop, stopped) = oneshot::channel();
+        let task = tokio::spawn(http::serve(
+            listener,
+            Arc::new(BazImpl::new(store)),
+            async {
+                let _ = stopped.await;
+            },
+        ));
+        Self { addr, stop, task }
+    }
+
+    /// Sends one HTTP/1.1 request and returns the status and JSON body.
+    async fn request(&self, method: &str, path: &str, body: &str) -> (u16, Value) {
+        let mut stream = TcpStream::connect(self.addr).await.unwrap();
+        let request = format!(
+            "{method} {path} HTTP/1.1\r\n\
+             Host: localhost\r\n\
+             Content-Type: application/json\r\n\
+             Content-Length: {}\r\n\
+             Connection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    async fn stop(self) {
+        self.stop.send(()).unwrap();
+        self.task.await.unwrap().unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_operations_update_the_served_state() {
+    let server = Server::start(InMemoryFoo::default()).await;
+
+    assert_eq!(
+        server.request("GET", "/health", "").await,
+        (200, json!({ "status": "ok" }))
+    );
+
+    let (status, _) = server
+        .request("POST", "/operations", r#"{"op":"set_name","name":"http"}"#)
+        .await;
+    assert_eq!(status, 200);
+    let (status, zed) = server
+        .request("POST", "/operations", r#"{"op":"increment","by":3}"#)
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(zed["counter"], 3);
+
+    let (status, zed) = server.request("GET", "/zed", "").await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        zed,
+        json!({ "name": "http", "counter": 3, "entries": [], "version": 0 })
+    );
+
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn test_reading_the_state_does_not_write_it() {
+    // Every write to a VersionedFoo bumps the version.
+    let server = Server::start(VersionedFoo::default()).await;
+
+    for _ in 0..2 {
+        let (status, zed) = server.request("GET", "/zed", "").await;
+        assert_eq!(status, 200);
+        assert_eq!(zed["version"], 0);
+    }
+
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn test_failures_map_to_status_codes() {
+    let server = Server::start(InMemoryFoo::default()).await;
+
+    let (status, body) = server
+        .request("POST", "/operations", r#"{"op":"append_entry","entry":""}"#)
+        .await;
+    assert_eq!(status, 422);
+    assert_eq!(body, json!({ "error": "update rejected: entry is empty" }));
+
+    let (status, body) = server
+        .request("POST", "/operations", r#"{"op":"launch"}"#)
+        .await;
+    assert_eq!(status, 400);
+    assert!(body["error"].is_string());
+
+    server.stop().await;
+
+    let failing = ChaosFoo::new(
+        InMemoryFoo::default(),
+        ChaosPolicy {
+            error_rate: 1.0,
+            ..ChaosPolicy::default()
+        },
+    );
+    let server = Server::start(failing).await;
+    let (status, _) = server.request("GET", "/zed", "").await;
+    assert_eq!(status, 503);
+    server.stop().await;
+}